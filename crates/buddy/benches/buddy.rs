@@ -0,0 +1,100 @@
+// Compares the buddy allocator with the bitmap the frame allocator used to scan for free frames.
+// Run with `cargo bench` from this directory.
+
+use buddy::{Buddy, Link, bitmap_len};
+use criterion::{BenchmarkId, Criterion, black_box, criterion_group, criterion_main};
+
+/// The old frame allocator: a bit for every frame, set if it's in use,
+/// and a linear search for the first run of free frames that's big enough.
+mod bitmap {
+    pub fn get_bit(bytes: &[u8], index: usize) -> bool {
+        let mask = 0b10000000u8 >> (index % 8);
+        bytes[index / 8] & mask > 0
+    }
+
+    pub fn set_bits(bytes: &mut [u8], index: usize, count: usize, value: bool) {
+        for index in index..index + count {
+            let mask = 0b10000000u8 >> (index % 8);
+            if value {
+                bytes[index / 8] |= mask;
+            } else {
+                bytes[index / 8] &= !mask;
+            }
+        }
+    }
+
+    pub fn find_clear(bytes: &[u8], start: usize, end: usize, count: usize, align: usize) -> Option<usize> {
+        let mut begin = num_integer::div_ceil(start, align) * align;
+        while begin + count <= end {
+            match (begin..begin + count).rev().find(|&i| get_bit(bytes, i)) {
+                Some(used) => begin = num_integer::div_ceil(used + 1, align) * align,
+                None => return Some(begin),
+            }
+        }
+        None
+    }
+
+    pub fn allocate(bytes: &mut [u8], count: usize, align: usize) -> Option<usize> {
+        let begin = find_clear(bytes, 0, bytes.len() * 8, count, align)?;
+        set_bits(bytes, begin, count, true);
+        Some(begin)
+    }
+}
+
+const SIZES: [usize; 3] = [1 << 12, 1 << 16, 1 << 20];
+
+/// Allocate and free one frame when the first half of memory is already in use,
+/// which is about how things look once the kernel has been running for a while.
+fn single_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single frame, half full");
+    for &len in SIZES.iter() {
+        let mut bytes = vec![0; len / 8];
+        bitmap::set_bits(&mut bytes, 0, len / 2, true);
+        group.bench_with_input(BenchmarkId::new("bitmap", len), &len, |b, _| b.iter(|| {
+            let index = bitmap::allocate(&mut bytes, 1, 1).unwrap();
+            bitmap::set_bits(&mut bytes, black_box(index), 1, false);
+        }));
+
+        let mut links = vec![Link::EMPTY; len];
+        let mut words = vec![0; bitmap_len(len)];
+        let mut buddy = Buddy::new(len, links.as_mut_slice(), &mut words);
+        buddy.deallocate_range(len / 2, len / 2);
+        group.bench_with_input(BenchmarkId::new("buddy", len), &len, |b, _| b.iter(|| {
+            let index = buddy.allocate(0).unwrap();
+            buddy.deallocate(black_box(index), 0);
+        }));
+    }
+    group.finish();
+}
+
+/// Allocate and free a 2 MiB frame (for a huge page) when every 64th frame is in use,
+/// so that there's plenty of free memory, but only the last 2 MiB of it is contiguous.
+fn huge_frame(c: &mut Criterion) {
+    let mut group = c.benchmark_group("2 MiB frame, fragmented");
+    for &len in SIZES.iter() {
+        let mut bytes = vec![0; len / 8];
+        for index in (0..len - 512).step_by(64) {
+            bitmap::set_bits(&mut bytes, index, 1, true);
+        }
+        group.bench_with_input(BenchmarkId::new("bitmap", len), &len, |b, _| b.iter(|| {
+            let index = bitmap::allocate(&mut bytes, 512, 512).unwrap();
+            bitmap::set_bits(&mut bytes, black_box(index), 512, false);
+        }));
+
+        let mut links = vec![Link::EMPTY; len];
+        let mut words = vec![0; bitmap_len(len)];
+        let mut buddy = Buddy::new(len, links.as_mut_slice(), &mut words);
+        for index in (0..len - 512).step_by(64) {
+            buddy.deallocate_range(index + 1, 63);
+        }
+        buddy.deallocate_range(len - 512, 512);
+        group.bench_with_input(BenchmarkId::new("buddy", len), &len, |b, _| b.iter(|| {
+            let index = buddy.allocate(9).unwrap();
+            buddy.deallocate(black_box(index), 9);
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, single_frame, huge_frame);
+criterion_main!(benches);
@@ -0,0 +1,222 @@
+// A buddy allocator hands out blocks of 2^order pages, aligned to their own size.
+// Every block of order n > 0 is made of two "buddies" of order n - 1,
+// so a free block can be split in half to make a smaller one, and when both halves are free again,
+// they get merged back together. Keeping a list of the free blocks of each order
+// means allocating and freeing take O(MAX_ORDER) steps, no matter how much memory there is.
+//
+// The free lists are doubly linked, so that a block can be taken off its list
+// as soon as its buddy is freed. Only the first page of a free block needs a list entry,
+// so it's up to whoever owns the pages where the entries go (see `Links`):
+// the frame allocator keeps them in the free frames themselves, where they cost nothing.
+// Besides the lists, the allocator needs to know whether a page starts a free block of some order,
+// which takes a bitmap for each order, or about two bits per page altogether.
+//
+// This doesn't depend on anything else in the kernel, so it's built as its own crate,
+// which means it can be tested and benchmarked on the host.
+
+#![no_std]
+// Struct fields are initialized the same way as in the kernel.
+#![allow(clippy::redundant_field_names)]
+
+/// The largest block we keep track of is 2^MAX_ORDER pages, which is 1 GiB of 4 KiB pages.
+pub const MAX_ORDER: usize = 18;
+
+/// The end of a free list.
+pub const NIL: usize = usize::MAX;
+
+/// An entry in the doubly-linked free list of a block's order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Link {
+    pub next: usize,
+    pub prev: usize,
+}
+
+impl Link {
+    pub const EMPTY: Link = Link { next: NIL, prev: NIL };
+}
+
+/// Where the free list entries of an allocator's pages are kept.
+///
+/// The allocator only ever reads the entry of a page which it has set since the page was last freed,
+/// and only ever sets the entry of the first page of a free block.
+pub trait Links {
+    fn get(&self, index: usize) -> Link;
+    fn set(&mut self, index: usize, link: Link);
+}
+
+/// Entries kept on the side, with one for every page.
+impl Links for &mut [Link] {
+    fn get(&self, index: usize) -> Link {
+        self[index]
+    }
+
+    fn set(&mut self, index: usize, link: Link) {
+        self[index] = link;
+    }
+}
+
+/// The smallest order of block which holds `pages` pages.
+pub fn order_of(pages: usize) -> usize {
+    pages.next_power_of_two().trailing_zeros() as usize
+}
+
+/// The number of bits of bitmap an allocator for `len` pages needs: one for every block of every order.
+fn bitmap_bits(len: usize) -> usize {
+    (0..=MAX_ORDER).map(|order| len >> order).sum()
+}
+
+/// The number of words of bitmap an allocator for `len` pages needs.
+pub fn bitmap_len(len: usize) -> usize {
+    num_integer::div_ceil(bitmap_bits(len), 64)
+}
+
+/// Allocates runs of pages numbered from zero to `len`.
+/// Every page starts out used; use `deallocate_range` to say which ones can be allocated.
+pub struct Buddy<'a, L> {
+    len: usize,
+    links: L,
+    /// For each order, a bit for every block of that order, which is set if it's a free block.
+    /// (A free block isn't free as far as the orders above or below it are concerned.)
+    bitmap: &'a mut [u64],
+    /// The bit that each order's bits start from.
+    offsets: [usize; MAX_ORDER + 1],
+    free_lists: [usize; MAX_ORDER + 1],
+    free: usize,
+}
+
+impl<'a, L: Links> Buddy<'a, L> {
+    /// Create an allocator for `len` pages, which keeps its free lists in `links`
+    /// and its bitmap in `bitmap`, which must be at least `bitmap_len(len)` words long.
+    /// The bitmap's contents don't matter.
+    pub fn new(len: usize, links: L, bitmap: &'a mut [u64]) -> Buddy<'a, L> {
+        assert!(bitmap.len() >= bitmap_len(len));
+        assert!(len < NIL);
+        for word in bitmap.iter_mut() {
+            *word = 0;
+        }
+        let mut offsets = [0; MAX_ORDER + 1];
+        for order in 1..=MAX_ORDER {
+            offsets[order] = offsets[order - 1] + (len >> (order - 1));
+        }
+        Buddy {
+            len: len,
+            links: links,
+            bitmap: bitmap,
+            offsets: offsets,
+            free_lists: [NIL; MAX_ORDER + 1],
+            free: 0,
+        }
+    }
+
+    /// The number of pages which are free.
+    pub fn free(&self) -> usize {
+        self.free
+    }
+
+    /// The word and mask of the bit for the block of 2^`order` pages starting at `index`.
+    fn bit(&self, index: usize, order: usize) -> (usize, u64) {
+        let bit = self.offsets[order] + (index >> order);
+        (bit / 64, 1 << (bit % 64))
+    }
+
+    /// Whether there's a free block of 2^`order` pages starting at `index`,
+    /// which must be a multiple of 2^`order`.
+    fn is_free_block(&self, index: usize, order: usize) -> bool {
+        if index + (1 << order) > self.len {
+            return false;
+        }
+        let (word, mask) = self.bit(index, order);
+        self.bitmap[word] & mask != 0
+    }
+
+    fn push(&mut self, index: usize, order: usize) {
+        let head = self.free_lists[order];
+        self.links.set(index, Link { next: head, prev: NIL });
+        if head != NIL {
+            let link = self.links.get(head);
+            self.links.set(head, Link { prev: index, ..link });
+        }
+        self.free_lists[order] = index;
+        let (word, mask) = self.bit(index, order);
+        self.bitmap[word] |= mask;
+        self.free += 1 << order;
+    }
+
+    fn remove(&mut self, index: usize, order: usize) {
+        let Link { next, prev } = self.links.get(index);
+        if prev == NIL {
+            self.free_lists[order] = next;
+        } else {
+            let link = self.links.get(prev);
+            self.links.set(prev, Link { next: next, ..link });
+        }
+        if next != NIL {
+            let link = self.links.get(next);
+            self.links.set(next, Link { prev: prev, ..link });
+        }
+        let (word, mask) = self.bit(index, order);
+        self.bitmap[word] &= !mask;
+        self.free -= 1 << order;
+    }
+
+    /// Allocate a block of 2^`order` pages, returning the index of its first page,
+    /// which is a multiple of 2^`order`.
+    pub fn allocate(&mut self, order: usize) -> Option<usize> {
+        let mut current = (order..=MAX_ORDER).find(|&current| self.free_lists[current] != NIL)?;
+        let index = self.free_lists[current];
+        self.remove(index, current);
+        // We only need the first half of the block, so the second half goes back on the free lists,
+        // until the block is the right size.
+        while current > order {
+            current -= 1;
+            self.push(index + (1 << current), current);
+        }
+        Some(index)
+    }
+
+    /// Allocate `count` pages in a row, starting at a multiple of 2^`align_order` pages.
+    /// There's no such thing as allocating no pages, so that always fails.
+    ///
+    /// The pages come from the smallest block that can hold them,
+    /// and whatever's left over at the end of the block is freed again.
+    pub fn allocate_range(&mut self, count: usize, align_order: usize) -> Option<usize> {
+        if count == 0 {
+            return None;
+        }
+        let order = order_of(count).max(align_order);
+        let index = self.allocate(order)?;
+        self.deallocate_range(index + count, (1 << order) - count);
+        Some(index)
+    }
+
+    /// Free a block which was allocated with `allocate`.
+    pub fn deallocate(&mut self, index: usize, order: usize) {
+        let mut index = index;
+        let mut order = order;
+        while order < MAX_ORDER {
+            let buddy = index ^ (1 << order);
+            if !self.is_free_block(buddy, order) {
+                break;
+            }
+            self.remove(buddy, order);
+            index = index.min(buddy);
+            order += 1;
+        }
+        self.push(index, order);
+    }
+
+    /// Free `count` pages starting at `index`, which don't have to have been allocated as a block.
+    pub fn deallocate_range(&mut self, index: usize, count: usize) {
+        let end = index + count;
+        let mut index = index;
+        while index < end {
+            // Free the largest block that starts here and doesn't go past the end.
+            let mut order = (index.trailing_zeros() as usize).min(MAX_ORDER);
+            while index + (1 << order) > end {
+                order -= 1;
+            }
+            self.deallocate(index, order);
+            index += 1 << order;
+        }
+    }
+}
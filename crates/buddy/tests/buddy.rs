@@ -0,0 +1,153 @@
+use buddy::{Buddy, Link, Links, MAX_ORDER, bitmap_len, order_of};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// Pages that keep their own list entries, like physical frames do,
+/// so that we notice if the allocator touches a page that isn't the start of a free block.
+struct Memory {
+    links: Vec<Option<Link>>,
+    used: Vec<bool>,
+}
+
+impl Memory {
+    fn set_used(&mut self, index: usize, count: usize, used: bool) {
+        for page in index..index + count {
+            assert_ne!(self.used[page], used, "page {} was allocated or freed twice", page);
+            self.used[page] = used;
+            // Whoever allocated the page is free to scribble all over it.
+            self.links[page] = None;
+        }
+    }
+}
+
+struct PageLinks(Rc<RefCell<Memory>>);
+
+impl Links for PageLinks {
+    fn get(&self, index: usize) -> Link {
+        let memory = self.0.borrow();
+        assert!(!memory.used[index], "read the link of used page {}", index);
+        memory.links[index].expect("read a link that was never written")
+    }
+
+    fn set(&mut self, index: usize, link: Link) {
+        let mut memory = self.0.borrow_mut();
+        assert!(!memory.used[index], "wrote the link of used page {}", index);
+        memory.links[index] = Some(link);
+    }
+}
+
+/// A tiny xorshift generator, so that failures are reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
+
+#[test]
+fn starts_out_used() {
+    let mut links = vec![Link::EMPTY; 64];
+    let mut bitmap = vec![0; bitmap_len(64)];
+    let mut buddy = Buddy::new(64, links.as_mut_slice(), &mut bitmap);
+    assert_eq!(buddy.free(), 0);
+    assert_eq!(buddy.allocate(0), None);
+}
+
+#[test]
+fn allocates_aligned_blocks() {
+    let mut links = vec![Link::EMPTY; 1000];
+    let mut bitmap = vec![0; bitmap_len(1000)];
+    let mut buddy = Buddy::new(1000, links.as_mut_slice(), &mut bitmap);
+    buddy.deallocate_range(3, 997);
+    assert_eq!(buddy.free(), 997);
+    for order in 0..6 {
+        let index = buddy.allocate(order).unwrap();
+        assert_eq!(index % (1 << order), 0);
+        assert!(index >= 3 && index + (1 << order) <= 1000);
+    }
+    assert_eq!(buddy.allocate(MAX_ORDER), None);
+}
+
+#[test]
+fn empty_ranges_fail() {
+    let mut links = vec![Link::EMPTY; 16];
+    let mut bitmap = vec![0; bitmap_len(16)];
+    let mut buddy = Buddy::new(16, links.as_mut_slice(), &mut bitmap);
+    buddy.deallocate_range(0, 16);
+    assert_eq!(buddy.allocate_range(0, 0), None);
+    assert_eq!(buddy.allocate_range(0, 3), None);
+    assert_eq!(buddy.free(), 16);
+}
+
+#[test]
+fn merges_buddies() {
+    let mut links = vec![Link::EMPTY; 1 << 10];
+    let mut bitmap = vec![0; bitmap_len(1 << 10)];
+    let mut buddy = Buddy::new(1 << 10, links.as_mut_slice(), &mut bitmap);
+    buddy.deallocate_range(0, 1 << 10);
+    let blocks: Vec<usize> = (0..1 << 10).map(|_| buddy.allocate(0).unwrap()).collect();
+    assert_eq!(buddy.free(), 0);
+    for index in blocks {
+        buddy.deallocate(index, 0);
+    }
+    // If everything merged back together, the whole thing is one block again.
+    assert_eq!(buddy.allocate(10), Some(0));
+}
+
+/// Compare random allocations against a list of which pages are used.
+#[test]
+fn matches_model() {
+    const LEN: usize = 5000;
+    let memory = Rc::new(RefCell::new(Memory { links: vec![None; LEN], used: vec![true; LEN] }));
+    let mut bitmap = vec![0; bitmap_len(LEN)];
+    let mut buddy = Buddy::new(LEN, PageLinks(memory.clone()), &mut bitmap);
+    let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+
+    // Only some of the pages start out free, like physical memory with holes in it.
+    let mut index = 0;
+    while index < LEN {
+        let count = (1 + rng.below(300)).min(LEN - index);
+        if rng.below(3) > 0 {
+            memory.borrow_mut().set_used(index, count, false);
+            buddy.deallocate_range(index, count);
+        }
+        index += count;
+    }
+
+    let mut live: Vec<(usize, usize)> = Vec::new();
+    for _ in 0..100_000 {
+        if rng.below(2) == 0 || live.is_empty() {
+            let count = 1 + rng.below(40);
+            let align_order = rng.below(4);
+            match buddy.allocate_range(count, align_order) {
+                Some(index) => {
+                    assert_eq!(index % (1 << align_order), 0);
+                    memory.borrow_mut().set_used(index, count, true);
+                    live.push((index, count));
+                },
+                None => {
+                    // It's only allowed to fail if there isn't a whole free block big enough.
+                    let order = order_of(count).max(align_order);
+                    let memory = memory.borrow();
+                    let used = &memory.used;
+                    let fits = (0..LEN >> order)
+                        .any(|block| used[block << order..(block + 1) << order].iter().all(|&used| !used));
+                    assert!(!fits, "failed to allocate {} pages aligned to order {}", count, align_order);
+                },
+            }
+        } else {
+            let (index, count) = live.swap_remove(rng.below(live.len()));
+            memory.borrow_mut().set_used(index, count, false);
+            buddy.deallocate_range(index, count);
+        }
+        assert_eq!(buddy.free(), memory.borrow().used.iter().filter(|&&used| !used).count());
+    }
+}
@@ -0,0 +1,36 @@
+// The kernel's fonts, built for the host so that they can be tested.
+//
+// The fonts don't depend on anything but each other and `pc_screen_font`,
+// which we stand in for, since PSF fonts are parsed by that crate rather than by the kernel.
+#![no_std]
+
+extern crate alloc;
+// The kernel refers to `pc_screen_font` by name, so that name has to lead to the stand-in.
+extern crate self as pc_screen_font;
+
+// Struct fields are initialized the same way as in the kernel.
+#[allow(clippy::redundant_field_names)]
+#[path = "../../../src/graphics"]
+pub mod graphics {
+    pub mod font;
+}
+
+/// Just enough of `pc_screen_font::Font` for the kernel's font module to build.
+pub struct Font;
+
+impl Font {
+    pub fn parse(_data: &[u8]) -> Result<Font, ParseError> { Err(ParseError) }
+    pub fn bounding_box(&self) -> (usize, usize) { (0, 0) }
+    pub fn lookup(&self, _ch: char) -> Option<&Glyph> { None }
+}
+
+pub struct Glyph;
+
+#[derive(Debug)]
+pub struct ParseError;
+
+impl Glyph {
+    pub fn width(&self) -> usize { 0 }
+    pub fn height(&self) -> usize { 0 }
+    pub fn get(&self, _x: usize, _y: usize) -> Option<bool> { None }
+}
@@ -0,0 +1,234 @@
+// Parses small BDF fonts written out by hand, and checks where each glyph ends up in the font's bounding box.
+
+use font_tests::graphics::font::{Font, Glyph};
+use font_tests::graphics::font::bdf::{BdfError, BdfFont};
+
+/// A 6x13 font whose baseline is 3 pixels above the bottom, like Cozette's.
+const FONT: &str = "\
+STARTFONT 2.1
+FONT -misc-test-medium-r-normal--13-120-75-75-c-60-iso10646-1
+SIZE 12 75 75
+FONTBOUNDINGBOX 6 13 0 -3
+STARTPROPERTIES 1
+FONT_ASCENT 10
+ENDPROPERTIES
+CHARS 5
+STARTCHAR A
+ENCODING 65
+SWIDTH 500 0
+DWIDTH 6 0
+BBX 5 7 0 0
+BITMAP
+20
+50
+88
+F8
+88
+88
+88
+ENDCHAR
+STARTCHAR g
+ENCODING 103
+SWIDTH 500 0
+DWIDTH 6 0
+BBX 4 6 1 -3
+BITMAP
+70
+90
+90
+70
+10
+E0
+ENDCHAR
+STARTCHAR period
+ENCODING 46
+SWIDTH 500 0
+DWIDTH 6 0
+BBX 1 1 2 0
+BITMAP
+80
+ENDCHAR
+STARTCHAR overhang
+ENCODING 94
+SWIDTH 500 0
+DWIDTH 6 0
+BBX 3 3 -1 9
+BITMAP
+E0
+A0
+E0
+ENDCHAR
+STARTCHAR unencoded
+ENCODING -1
+SWIDTH 500 0
+DWIDTH 6 0
+BBX 1 1 0 0
+BITMAP
+80
+ENDCHAR
+ENDFONT
+";
+
+/// Draw a character the way the console would, in a cell the size of the font's bounding box,
+/// with one string per row. Pixels are `#` if they're covered, `.` if they aren't,
+/// and the level of coverage from 1 to 9 if they're somewhere in between.
+fn render(font: &dyn Font, ch: char) -> Vec<String> {
+    let (width, height) = font.bounding_box();
+    let glyph = font.lookup(ch).unwrap_or_else(|| panic!("{:?} is missing", ch));
+    let (offset_x, offset_y) = glyph.offset();
+    assert!(offset_x + glyph.width() <= width && offset_y + glyph.height() <= height, "{:?} doesn't fit", ch);
+    let mut cell = vec![vec!['.'; width]; height];
+    for y in 0..glyph.height() {
+        for x in 0..glyph.width() {
+            cell[offset_y + y][offset_x + x] = match glyph.coverage(x, y) {
+                0 => '.',
+                0xFF => '#',
+                coverage => std::char::from_digit((coverage as u32 * 9 / 0xFF).max(1), 10).unwrap(),
+            };
+        }
+    }
+    cell.into_iter().map(|row| row.into_iter().collect()).collect()
+}
+
+fn parse(font: &str) -> Result<BdfFont, BdfError> {
+    BdfFont::parse(font.as_bytes())
+}
+
+#[test]
+fn places_glyphs_relative_to_the_baseline() {
+    let font = parse(FONT).unwrap();
+    assert_eq!(font.bounding_box(), (6, 13));
+    assert_eq!(render(&font, 'A'), [
+        "......",
+        "......",
+        "......",
+        "..#...",
+        ".#.#..",
+        "#...#.",
+        "#####.",
+        "#...#.",
+        "#...#.",
+        "#...#.",
+        "......",
+        "......",
+        "......",
+    ]);
+    assert_eq!(render(&font, 'g'), [
+        "......",
+        "......",
+        "......",
+        "......",
+        "......",
+        "......",
+        "......",
+        "..###.",
+        ".#..#.",
+        ".#..#.",
+        "..###.",
+        "....#.",
+        ".###..",
+    ]);
+    // Glyphs are only as big as they need to be.
+    let period = font.lookup('.').unwrap();
+    assert_eq!((period.width(), period.height(), period.offset()), (1, 1, (2, 9)));
+}
+
+#[test]
+fn cuts_off_glyphs_sticking_out_of_the_top_left() {
+    let font = parse(FONT).unwrap();
+    let glyph = font.lookup('^').unwrap();
+    assert_eq!((glyph.width(), glyph.height(), glyph.offset()), (2, 1, (0, 0)));
+    assert_eq!(render(&font, '^')[0], "##....");
+}
+
+#[test]
+fn skips_unencoded_glyphs() {
+    let font = parse(FONT).unwrap();
+    assert!(font.lookup('\u{0}').is_none());
+    assert!(font.lookup('B').is_none());
+}
+
+#[test]
+fn first_glyph_for_a_character_wins() {
+    // Enough glyphs that the sort can't get away with not moving any of them,
+    // with each character's glyphs far apart and a different pixel set in each.
+    let mut font = String::from("STARTFONT 2.1\nFONTBOUNDINGBOX 8 8 0 0\n");
+    for i in 0..64 {
+        let ch = b'a' + (i % 26) as u8;
+        font += &format!("STARTCHAR {}\nENCODING {}\nBBX 8 1 0 0\nBITMAP\n{:02X}\nENDCHAR\n", i, ch, 0x80 >> (i / 26));
+    }
+    font += "ENDFONT\n";
+    let font = parse(&font).unwrap();
+    for ch in 'a'..='z' {
+        assert_eq!(render(&font, ch)[7], "#.......", "{:?}", ch);
+    }
+}
+
+#[test]
+fn reads_grayscale_glyphs() {
+    let font = parse("\
+STARTFONT 2.3
+SIZE 8 75 75 2
+FONTBOUNDINGBOX 4 2 0 0
+STARTCHAR gradient
+ENCODING 65
+BBX 4 2 0 0
+BITMAP
+1B
+E4
+ENDCHAR
+ENDFONT
+").unwrap();
+    assert_eq!(render(&font, 'A'), [".36#", "#63."]);
+    let glyph = font.lookup('A').unwrap();
+    assert!(!glyph.get(1, 0) && glyph.get(2, 0));
+}
+
+#[test]
+fn rejects_malformed_fonts() {
+    let glyph = |bbx: &str, bitmap: &str| {
+        format!("STARTFONT 2.1\nFONTBOUNDINGBOX 6 13 0 -3\nSTARTCHAR x\nENCODING 120\nBBX {}\nBITMAP\n{}ENDCHAR\nENDFONT\n",
+            bbx, bitmap)
+    };
+    assert_eq!(parse("").err(), Some(BdfError::NotBdf));
+    assert_eq!(parse("FONTBOUNDINGBOX 6 13 0 -3\n").err(), Some(BdfError::NotBdf));
+    assert_eq!(parse("STARTFONT 2.1\nSTARTCHAR x\nENCODING 120\nBBX 1 1 0 0\n").err(),
+        Some(BdfError::MissingBoundingBox));
+    assert_eq!(parse("STARTFONT 2.1\nFONTBOUNDINGBOX 6 13 0 -3\nSTARTCHAR x\nENCODING 120\nBITMAP\n").err(),
+        Some(BdfError::MissingGlyphBoundingBox { line: 5 }));
+    assert_eq!(parse("STARTFONT 2.1\nSIZE 12 75 75 3\n").err(), Some(BdfError::UnsupportedDepth { line: 2 }));
+    assert_eq!(parse(&glyph("2 2 0 0", "C0\nZZ\n")).err(), Some(BdfError::BadBitmap { line: 8 }));
+    // 12 pixels wide takes two bytes.
+    assert_eq!(parse(&glyph("12 1 0 0", "FF\n")).err(), Some(BdfError::BadBitmap { line: 7 }));
+    assert_eq!(parse(&glyph("1 2 0 0", "80\n")).err(), Some(BdfError::BadBitmap { line: 8 }));
+    assert_eq!(parse(&glyph("1 1 0", "80\n")).err(), Some(BdfError::BadValue { line: 5 }));
+    let unfinished = glyph("1 1 0 0", "80\n");
+    assert_eq!(parse(&unfinished[..unfinished.find("ENDCHAR").unwrap()]).err(), Some(BdfError::UnexpectedEnd));
+}
+
+#[test]
+fn rejects_glyphs_whose_size_or_position_overflows() {
+    let glyph = |bbx: &str| {
+        format!("STARTFONT 2.1\nFONTBOUNDINGBOX 6 13 0 -3\nSTARTCHAR x\nENCODING 120\nBBX {}\nBITMAP\n80\nENDCHAR\nENDFONT\n",
+            bbx)
+    };
+    // Far bigger than the font.
+    assert_eq!(parse(&glyph("100 1 0 0")).err(), Some(BdfError::BadValue { line: 5 }));
+    assert_eq!(parse(&glyph("1 100 0 0")).err(), Some(BdfError::BadValue { line: 5 }));
+    assert_eq!(parse(&glyph(&format!("{} 1 0 0", usize::MAX))).err(), Some(BdfError::BadValue { line: 5 }));
+    // So far away that working out where it is overflows, which we only find out at the end of the glyph.
+    assert_eq!(parse(&glyph(&format!("1 1 0 {}", isize::MAX))).err(), Some(BdfError::BadValue { line: 8 }));
+    assert_eq!(parse(&glyph(&format!("1 1 0 {}", isize::MIN))).err(), Some(BdfError::BadValue { line: 8 }));
+    // Far away, but not so far that we can't tell; it's just cut off entirely.
+    let font = parse(&glyph(&format!("1 1 {} 0", isize::MIN))).unwrap();
+    assert_eq!(font.lookup('x').unwrap().width(), 0);
+}
+
+/// Cutting a font off anywhere must fail cleanly rather than panic.
+#[test]
+fn rejects_every_truncation() {
+    // The last byte is the newline after `ENDFONT`, which the font can do without.
+    for len in 0..FONT.len() - 1 {
+        assert!(BdfFont::parse(&FONT.as_bytes()[..len]).is_err(), "decoded with {} bytes", len);
+    }
+}
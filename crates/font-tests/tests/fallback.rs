@@ -0,0 +1,60 @@
+use font_tests::graphics::font::{Font, Glyph};
+use font_tests::graphics::font::bdf::{self, BdfFont};
+use font_tests::graphics::font::fallback::FallbackFont;
+
+/// A font with a full-size glyph for each of `chars`, in a bounding box of the given size,
+/// whose rows are all `row` (so that we can tell which font a glyph came from).
+fn font(size: (usize, usize), chars: &str, row: &str) -> BdfFont {
+    let (width, height) = size;
+    let mut font = format!("STARTFONT 2.1\nFONTBOUNDINGBOX {} {} 0 0\n", width, height);
+    for ch in chars.chars() {
+        font += &format!("STARTCHAR {}\nENCODING {}\nBBX {} {} 0 0\nBITMAP\n", ch, ch as u32, width, height);
+        font += &format!("{}\n", row).repeat(height);
+        font += "ENDCHAR\n";
+    }
+    font += "ENDFONT\n";
+    BdfFont::parse(font.as_bytes()).unwrap()
+}
+
+#[test]
+fn searches_fonts_in_order() {
+    let first = font((6, 13), "ab", "FC");
+    let second = font((6, 13), "bc", "80");
+    let font = FallbackFont::new(&[&first, &second]);
+    assert_eq!(font.bounding_box(), (6, 13));
+    // The first font's glyphs are filled in, and the second's only have their first column.
+    assert!(font.lookup('a').unwrap().get(5, 0));
+    assert!(font.lookup('b').unwrap().get(5, 0));
+    assert!(!font.lookup('c').unwrap().get(5, 0));
+    assert!(font.lookup('d').is_none());
+}
+
+#[test]
+fn centers_smaller_fonts() {
+    let first = font((6, 13), "a", "FC");
+    let second = font((4, 8), "b", "F0");
+    let font = FallbackFont::new(&[&first, &second]);
+    assert_eq!(font.lookup('a').unwrap().offset(), (0, 0));
+    let glyph = font.lookup('b').unwrap();
+    assert_eq!((glyph.width(), glyph.height(), glyph.offset()), (4, 8, (1, 2)));
+}
+
+#[test]
+fn lines_up_bigger_fonts_with_the_top_left() {
+    let first = font((6, 13), "a", "FC");
+    let second = font((8, 16), "b", "FF");
+    let font = FallbackFont::new(&[&first, &second]);
+    assert_eq!(font.lookup('b').unwrap().offset(), (0, 0));
+}
+
+#[test]
+fn loads_the_console_fallback_font() {
+    let font = BdfFont::parse(bdf::DEJAVU_SANS_MONO).unwrap();
+    // It fills in for Cozette, so it has to fit in Cozette's cells.
+    assert_eq!(font.bounding_box(), (6, 13));
+    // Symbols, math and scripts that Cozette doesn't have.
+    for ch in "✉≵ʡս𝙶".chars() {
+        let glyph = font.lookup(ch).unwrap_or_else(|| panic!("{:?} is missing", ch));
+        assert!(glyph.offset().0 + glyph.width() <= 6 && glyph.offset().1 + glyph.height() <= 13);
+    }
+}
@@ -0,0 +1,61 @@
+use font_tests::graphics::font::Glyph;
+use font_tests::graphics::font::hex_box::HexBoxGlyph;
+
+fn render(ch: char, size: (usize, usize)) -> Vec<String> {
+    let glyph = HexBoxGlyph::new(ch, size);
+    (0..glyph.height())
+        .map(|y| (0..glyph.width()).map(|x| if glyph.get(x, y) { '#' } else { '.' }).collect())
+        .collect()
+}
+
+#[test]
+fn draws_two_rows_of_digits_in_a_box() {
+    // U+2603, as 26 over 03.
+    assert_eq!(render('\u{2603}', (11, 15)), [
+        "###########",
+        "#.........#",
+        "#.###.###.#",
+        "#...#.#...#",
+        "#.###.###.#",
+        "#.#...#.#.#",
+        "#.###.###.#",
+        "#.........#",
+        "#.###.###.#",
+        "#.#.#...#.#",
+        "#.#.#.###.#",
+        "#.#.#...#.#",
+        "#.###.###.#",
+        "#.........#",
+        "###########",
+]);
+}
+
+#[test]
+fn squeezes_the_digits_into_narrow_boxes() {
+    // Without the sides of the box or any space between them, the digits run together, but they're still readable.
+    assert_eq!(render('\u{2603}', (6, 13)), [
+        "######",
+        "######",
+        "..##..",
+        "######",
+        "#..#.#",
+        "######",
+        "......",
+        "######",
+        "#.#..#",
+        "#.####",
+        "#.#..#",
+        "######",
+        "######",
+]);
+}
+
+#[test]
+fn leaves_the_box_empty_if_the_digits_dont_fit() {
+    // Six digits don't fit in a 6x13 box, even squeezed together.
+    let glyph = HexBoxGlyph::new('\u{1F600}', (6, 13));
+    assert!((1..12).all(|y| (1..5).all(|x| !glyph.get(x, y))));
+    assert!((0..13).all(|y| glyph.get(0, y) && glyph.get(5, y)));
+    let glyph = HexBoxGlyph::new('\u{2603}', (5, 13));
+    assert!((1..12).all(|y| (1..4).all(|x| !glyph.get(x, y))));
+}
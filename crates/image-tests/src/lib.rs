@@ -0,0 +1,37 @@
+// The kernel's image decoders, built for the host so that they can be tested.
+//
+// The decoders don't depend on anything but each other, a few graphics types, and the checksums,
+// so we build those straight from the kernel's sources, and stand in for the display driver.
+// Clippy checks the kernel's sources too, except for a few lints against the kernel's style:
+// it writes out `field: field` in full, `Color::into_rgb` borrows, `chunks_exact` predates `as_chunks`,
+// and types with a `new` don't all implement `Default`.
+#![no_std]
+
+extern crate alloc;
+
+#[allow(clippy::redundant_field_names, clippy::wrong_self_convention, clippy::chunks_exact_to_as_chunks)]
+#[path = "../../../src/graphics"]
+pub mod graphics {
+    pub mod color;
+    pub mod image;
+    pub mod rect;
+}
+
+#[allow(clippy::new_without_default)]
+#[path = "../../../src/checksum.rs"]
+pub mod checksum;
+
+pub mod driver {
+    pub mod graphic_display {
+        use crate::graphics::color::RGBA;
+        use crate::graphics::rect::Rect;
+
+        /// Just enough of a display for `Image::draw` to build.
+        pub trait GraphicDisplay {
+            fn resolution(&self) -> (usize, usize);
+            fn width(&self) -> usize { self.resolution().0 }
+            fn height(&self) -> usize { self.resolution().1 }
+            fn blit_blended(&mut self, pixels: &[RGBA], stride: usize, src: Rect, x: isize, y: isize);
+        }
+    }
+}
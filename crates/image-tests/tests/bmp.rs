@@ -0,0 +1,90 @@
+// Decodes bitmaps and checks them against reference PNGs, decoded by the `png` crate.
+// The bitmaps in `tests/bmp` are named for their header version and pixel format
+// (`Info_` for `BITMAPINFOHEADER`, `V3_` for bitfields with an alpha mask, ...),
+// or come from BMP Suite, and `_Top_Down` ones are stored from the top row down.
+
+mod common;
+
+use common::{assert_pixels, dir, name, reference};
+use image_tests::graphics::color::Color;
+use image_tests::graphics::image::{self, bmp, DecodeError};
+use std::fs;
+use std::path::PathBuf;
+
+/// Bitmaps with channels wider than 8 bits, which the reference truncates to 8 bits, where we round.
+const WIDE_CHANNELS: &[&str] = &["rgb32-111110", "rgba32-61754"];
+
+fn bitmaps() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir("bmp")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("bmp".as_ref()))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn read(file: &str) -> Vec<u8> {
+    fs::read(dir("bmp").join(file)).unwrap()
+}
+
+#[test]
+fn matches_reference() {
+    let paths: Vec<PathBuf> = bitmaps().into_iter().filter(|path| path.with_extension("png").exists()).collect();
+    assert!(paths.len() >= 30);
+    for path in paths {
+        let image = bmp::decode(&fs::read(&path).unwrap()).unwrap_or_else(|err| panic!("{}: {}", name(&path), err));
+        let (width, height, mut expected) = reference(&fs::read(path.with_extension("png")).unwrap());
+        if WIDE_CHANNELS.contains(&name(&path)) {
+            for (pixel, expected) in image.pixels().iter().zip(&mut expected) {
+                for (actual, expected) in [pixel.r(), pixel.g(), pixel.b(), pixel.a()].iter().zip(expected.iter_mut()) {
+                    assert!((*actual as i16 - *expected as i16).abs() <= 1, "{}: {:?} rounds to {:?}", name(&path), expected, actual);
+                    *expected = *actual;
+                }
+            }
+        }
+        assert_pixels(name(&path), &image, (width, height, expected));
+    }
+}
+
+#[test]
+fn top_down_bitmaps_match_bottom_up_ones() {
+    for path in bitmaps().into_iter().filter(|path| name(path).ends_with("_Top_Down")) {
+        let bottom_up = dir("bmp").join(name(&path).replace("_Top_Down", "") + ".bmp");
+        let (top_down, bottom_up) = (fs::read(&path).unwrap(), fs::read(bottom_up).unwrap());
+        assert_ne!(top_down, bottom_up);
+        let (top_down, bottom_up) = (bmp::decode(&top_down).unwrap(), bmp::decode(&bottom_up).unwrap());
+        assert!(top_down.pixels() == bottom_up.pixels(), "{}", name(&path));
+    }
+}
+
+#[test]
+fn is_detected_by_its_signature() {
+    let image = image::decode(&read("rgb24.bmp")).unwrap();
+    assert_eq!((image.width(), image.height()), (127, 64));
+}
+
+#[test]
+fn rejects_unsupported_bitmaps() {
+    assert_eq!(bmp::decode(&read("Core_1_Bit.bmp")).err(), Some(DecodeError::Unsupported("OS/2 bitmap header")));
+    for file in &["pal4rle.bmp", "pal8rle.bmp"] {
+        assert_eq!(bmp::decode(&read(file)).err(), Some(DecodeError::Unsupported("compressed bitmap")), "{}", file);
+    }
+    assert_eq!(bmp::decode(&read("pal8badindex.bmp")).err(), Some(DecodeError::Malformed("color index out of range")));
+}
+
+/// Cutting a bitmap off anywhere must fail cleanly rather than panic.
+/// The `Info_` and `V3_` bitmaps end with two bytes of padding after the pixels, which they can do without.
+#[test]
+fn rejects_every_truncation() {
+    for path in bitmaps() {
+        let data = fs::read(&path).unwrap();
+        let full = bmp::decode(&data).ok();
+        for len in 0..data.len() {
+            match (bmp::decode(&data[..len]), &full) {
+                (Err(_), _) => {},
+                (Ok(image), Some(full)) if image.pixels() == full.pixels() && len >= data.len() - 2 => {},
+                _ => panic!("{} decoded with {} bytes", name(&path), len),
+            }
+        }
+    }
+}
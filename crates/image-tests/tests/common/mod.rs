@@ -0,0 +1,44 @@
+// Helpers shared by the tests of each format.
+
+use image_tests::graphics::color::Color;
+use image_tests::graphics::image::Image;
+use std::path::{Path, PathBuf};
+
+/// An image's width, height and pixels, as 8-bit RGBA.
+pub type Pixels = (usize, usize, Vec<[u8; 4]>);
+
+pub fn dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name)
+}
+
+pub fn name(path: &Path) -> &str {
+    path.file_stem().unwrap().to_str().unwrap()
+}
+
+/// Decode a PNG with the `png` crate, as 8-bit RGBA.
+pub fn reference(data: &[u8]) -> Pixels {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().unwrap();
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).unwrap();
+    let mut pixels: Vec<[u8; 4]> = match info.color_type {
+        png::ColorType::Grayscale => buf.iter().map(|&g| [g, g, g, 0xFF]).collect(),
+        png::ColorType::GrayscaleAlpha => buf.as_chunks().0.iter().map(|&[g, a]| [g, g, g, a]).collect(),
+        png::ColorType::Rgb => buf.as_chunks().0.iter().map(|&[r, g, b]| [r, g, b, 0xFF]).collect(),
+        png::ColorType::Rgba => buf.as_chunks().0.to_vec(),
+        png::ColorType::Indexed => unreachable!("palette images are expanded"),
+    };
+    pixels.truncate(info.width as usize * info.height as usize);
+    (info.width as usize, info.height as usize, pixels)
+}
+
+/// Check that `image` has the given size and pixels, naming the image `name` if it doesn't.
+pub fn assert_pixels(name: &str, image: &Image, expected: Pixels) {
+    let (width, height, expected) = expected;
+    assert_eq!((image.width(), image.height()), (width, height), "{}", name);
+    for (i, (pixel, expected)) in image.pixels().iter().zip(expected).enumerate() {
+        let actual = [pixel.r(), pixel.g(), pixel.b(), pixel.a()];
+        assert_eq!(actual, expected, "{}: pixel ({}, {})", name, i % width, i / width);
+    }
+}
@@ -0,0 +1,79 @@
+// Decodes PngSuite (http://www.schaik.com/pngsuite/), the standard set of PNG test images,
+// and checks every image against the `png` crate. PngSuite has its own license (see `tests/pngsuite/LICENSE`).
+// Its images are named for what's in them: the first letter is the category
+// (`b`asic, `s`izes, `t`ransparency, ...), and images starting with `x` are corrupt.
+
+mod common;
+
+use common::{assert_pixels, dir, name, reference};
+use image_tests::checksum;
+use image_tests::graphics::image::{self, DecodeError};
+use std::fs;
+use std::path::PathBuf;
+
+fn suite() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir("pngsuite")).unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension() == Some("png".as_ref()))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn read(file: &str) -> Vec<u8> {
+    fs::read(dir("pngsuite").join(file)).unwrap()
+}
+
+/// The image with a chunk of type `kind` added right after the header.
+fn with_chunk(data: &[u8], kind: &[u8], contents: &[u8]) -> Vec<u8> {
+    // The signature is 8 bytes, and the header chunk is 25.
+    let mut out = data[..33].to_vec();
+    out.extend_from_slice(&(contents.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(contents);
+    let crc = checksum::crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+    out.extend_from_slice(&data[33..]);
+    out
+}
+
+#[test]
+fn matches_reference() {
+    let paths: Vec<PathBuf> = suite().into_iter().filter(|path| !name(path).starts_with('x')).collect();
+    assert!(paths.len() >= 150);
+    for path in paths {
+        let data = fs::read(&path).unwrap();
+        let image = image::decode(&data).unwrap_or_else(|err| panic!("{}: {}", name(&path), err));
+        assert_pixels(name(&path), &image, reference(&data));
+    }
+}
+
+#[test]
+fn ignores_transparency_chunks_on_images_with_alpha() {
+    // The `png` crate rejects these, so we compare with the image as it was.
+    for file in &["basn4a08.png", "basn6a08.png", "basn6a16.png"] {
+        let data = read(file);
+        let image = image::decode(&with_chunk(&data, b"tRNS", &[0, 0])).unwrap();
+        assert_pixels(file, &image, reference(&data));
+    }
+}
+
+#[test]
+fn rejects_corrupt_images() {
+    for path in suite().into_iter().filter(|path| name(path).starts_with('x')) {
+        assert!(image::decode(&fs::read(&path).unwrap()).is_err(), "{} decoded", name(&path));
+    }
+    assert_eq!(image::decode(&read("xcsn0g01.png")).err(), Some(DecodeError::Malformed("chunk CRC doesn't match")));
+}
+
+/// Cutting an image off anywhere must fail cleanly rather than panic.
+#[test]
+fn rejects_every_truncation() {
+    for path in suite() {
+        let data = fs::read(&path).unwrap();
+        for len in 0..data.len() {
+            assert!(image::decode(&data[..len]).is_err(), "{} decoded with {} bytes", name(&path), len);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+// Decodes QOI images and checks them against the `qoi` crate. `tests/qoi/basic-test.qoi` comes from the test suite
+// of the `image` crate (see `tests/bmp/README`), and the rest are the reference images for the bitmap tests,
+// encoded by the `qoi` crate.
+
+mod common;
+
+use common::{assert_pixels, dir, name, reference, Pixels};
+use image_tests::graphics::image::{self, qoi as qoi_image, DecodeError};
+use std::fs;
+
+/// The size of the end marker at the end of every image, which we don't need.
+const END_MARKER_SIZE: usize = 8;
+
+/// Decode a QOI image with the `qoi` crate, as 8-bit RGBA.
+fn qoi_reference(data: &[u8]) -> Pixels {
+    let (header, pixels) = qoi::decode_to_vec(data).unwrap();
+    let pixels = match header.channels {
+        qoi::Channels::Rgb => pixels.as_chunks().0.iter().map(|&[r, g, b]| [r, g, b, 0xFF]).collect(),
+        qoi::Channels::Rgba => pixels.as_chunks().0.to_vec(),
+    };
+    (header.width as usize, header.height as usize, pixels)
+}
+
+/// The bitmap tests' reference images, and their names.
+fn references() -> Vec<(String, Pixels)> {
+    let mut paths: Vec<_> = fs::read_dir(dir("bmp")).unwrap().map(|entry| entry.unwrap().path()).collect();
+    paths.sort();
+    paths.into_iter()
+        .filter(|path| path.extension() == Some("png".as_ref()))
+        .map(|path| (name(&path).to_string(), reference(&fs::read(&path).unwrap())))
+        .collect()
+}
+
+/// Encode an image with the `qoi` crate, with an alpha channel only if it needs one.
+fn encode(width: usize, height: usize, pixels: &[[u8; 4]]) -> Vec<u8> {
+    if pixels.iter().all(|pixel| pixel[3] == 0xFF) {
+        let rgb: Vec<u8> = pixels.iter().flat_map(|pixel| pixel[..3].to_vec()).collect();
+        qoi::encode_to_vec(&rgb, width as u32, height as u32).unwrap()
+    } else {
+        qoi::encode_to_vec(pixels.concat(), width as u32, height as u32).unwrap()
+    }
+}
+
+#[test]
+fn matches_reference() {
+    let data = fs::read(dir("qoi").join("basic-test.qoi")).unwrap();
+    let image = image::decode(&data).unwrap();
+    assert_pixels("basic-test", &image, qoi_reference(&data));
+
+    let images: Vec<(String, Vec<u8>)> = references().into_iter()
+        .map(|(name, (width, height, pixels))| (name, encode(width, height, &pixels)))
+        .collect();
+    assert!(images.iter().any(|(_, data)| data[12] == 3) && images.iter().any(|(_, data)| data[12] == 4));
+    for (name, data) in images {
+        let image = qoi_image::decode(&data).unwrap_or_else(|err| panic!("{}: {}", name, err));
+        assert_pixels(&name, &image, qoi_reference(&data));
+    }
+}
+
+#[test]
+fn rejects_malformed_images() {
+    let header = |width: u32, height: u32, channels: u8| {
+        let mut data = b"qoif".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[channels, 0]);
+        data
+    };
+    let end = [0, 0, 0, 0, 0, 0, 0, 1];
+    assert_eq!(qoi_image::decode(&[header(1, 1, 5), vec![0xFE, 1, 2, 3], end.to_vec()].concat()).err(),
+        Some(DecodeError::Malformed("bad channel count")));
+    // A run of 3 pixels in a 2-pixel image.
+    assert_eq!(qoi_image::decode(&[header(2, 1, 4), vec![0xC2], end.to_vec()].concat()).err(),
+        Some(DecodeError::Malformed("run goes past the end of the image")));
+    // Far too big to allocate, even though it's one long run.
+    assert_eq!(qoi_image::decode(&[header(1 << 16, 1 << 16, 4), vec![0xFD], end.to_vec()].concat()).err(),
+        Some(DecodeError::BadSize { width: 1 << 16, height: 1 << 16 }));
+}
+
+/// Cutting an image off anywhere before the end marker must fail cleanly rather than panic.
+#[test]
+fn rejects_every_truncation() {
+    let basic_test = ("basic-test".to_string(), fs::read(dir("qoi").join("basic-test.qoi")).unwrap());
+    // Cutting off whole images takes a while, so we only take the top left corner of each one,
+    // which is still enough to cover every kind of chunk.
+    let corners = references().into_iter().map(|(name, (width, height, pixels))| {
+        let (corner_width, corner_height) = (width.min(16), height.min(16));
+        let corner: Vec<[u8; 4]> = pixels.chunks(width).take(corner_height)
+            .flat_map(|row| row[..corner_width].to_vec())
+            .collect();
+        (name, encode(corner_width, corner_height, &corner))
+    });
+    for (name, data) in corners.chain(Some(basic_test)) {
+        let pixels_end = data.len() - END_MARKER_SIZE;
+        for len in 0..pixels_end {
+            assert!(qoi_image::decode(&data[..len]).is_err(), "{} decoded with {} bytes", name, len);
+        }
+        assert!(qoi_image::decode(&data[..pixels_end]).is_ok(), "{}", name);
+    }
+}
@@ -1,7 +1,21 @@
+use x86_64::VirtAddr;
 use x86_64::instructions::segmentation::{load_ss, set_cs};
+use x86_64::instructions::tables::load_tss;
 use x86_64::structures::gdt::{Descriptor, DescriptorFlags, GlobalDescriptorTable};
+use x86_64::structures::tss::TaskStateSegment;
+
+/// Entries in the interrupt stack table, for handlers which can't trust the stack they interrupted.
+/// The CPU always switches to the start of the stack when it calls one of these handlers.
+pub const DOUBLE_FAULT_STACK_INDEX: u16 = 0;
+pub const PAGE_FAULT_STACK_INDEX: u16 = 1;
+
+/// The page fault handler panics, and the panic screen needs a fair bit of stack to draw itself.
+const STACK_SIZE: usize = 32 * 1024;
 
 static mut GDT: GlobalDescriptorTable = GlobalDescriptorTable::new();
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+static mut DOUBLE_FAULT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+static mut PAGE_FAULT_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
 
 fn kernel_data_segment() -> Descriptor {
     use self::DescriptorFlags as Flags;
@@ -12,12 +26,20 @@ fn kernel_data_segment() -> Descriptor {
 
 pub fn load() {
     unsafe {
+        // Stacks grow down, so the CPU wants to know where they end.
+        TSS.interrupt_stack_table[DOUBLE_FAULT_STACK_INDEX as usize] =
+            VirtAddr::from_ptr(&DOUBLE_FAULT_STACK) + STACK_SIZE as u64;
+        TSS.interrupt_stack_table[PAGE_FAULT_STACK_INDEX as usize] =
+            VirtAddr::from_ptr(&PAGE_FAULT_STACK) + STACK_SIZE as u64;
+
         let cs = GDT.add_entry(Descriptor::kernel_code_segment());
         GDT.add_entry(Descriptor::user_code_segment());
         GDT.add_entry(Descriptor::user_data_segment());
         let ss = GDT.add_entry(kernel_data_segment());
+        let tss = GDT.add_entry(Descriptor::tss_segment(&TSS));
         GDT.load();
         set_cs(cs);
         load_ss(ss);
+        load_tss(tss);
     }
 }
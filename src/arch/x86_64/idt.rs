@@ -1,10 +1,18 @@
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+use crate::arch::x86_64::gdt;
+use x86_64::registers::control::Cr2;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
 
 static mut IDT: InterruptDescriptorTable = InterruptDescriptorTable::new();
 
 pub fn load() {
     unsafe {
         IDT.breakpoint.set_handler_fn(breakpoint_handler);
+        IDT.double_fault.set_handler_fn(double_fault_handler)
+            .set_stack_index(gdt::DOUBLE_FAULT_STACK_INDEX);
+        // The page fault handler gets its own stack too, because the most likely page fault of all
+        // is running off the end of the stack, and then there's no stack left to handle it on.
+        IDT.page_fault.set_handler_fn(page_fault_handler)
+            .set_stack_index(gdt::PAGE_FAULT_STACK_INDEX);
         IDT.load();
     }
 }
@@ -12,3 +20,16 @@ pub fn load() {
 extern "x86-interrupt" fn breakpoint_handler(_: &mut InterruptStackFrame) {
     log::info!("Breakpoint reached!");
 }
+
+// Now that we have our own page tables, this is what happens when you dereference a null pointer
+// or write to the kernel's code. There's nothing to recover, so we just report it.
+extern "x86-interrupt" fn page_fault_handler(frame: &mut InterruptStackFrame, error: PageFaultErrorCode) {
+    panic!("Page fault accessing {:?} at {:?} ({:?})", Cr2::read(), frame.instruction_pointer, error);
+}
+
+// A double fault is an exception that happened while the CPU was trying to call
+// the handler for another one, which we certainly can't recover from.
+// Without a handler (on a stack of its own), it would turn into a triple fault, which resets the computer.
+extern "x86-interrupt" fn double_fault_handler(frame: &mut InterruptStackFrame, _error: u64) -> ! {
+    panic!("Double fault at {:?}", frame.instruction_pointer);
+}
@@ -1,5 +1,7 @@
 pub mod gdt;
 pub mod idt;
+pub mod registers;
+pub mod stack;
 
 /// This macro exists because the x86_64 library uses `llvm_asm!`, which I have disabled.
 /// When the library ever uses plain `asm!` or a function, I will use its version instead.
@@ -0,0 +1,96 @@
+use core::fmt;
+
+/// A snapshot of the CPU's registers, for debugging (e.g. on the panic screen).
+///
+/// The general-purpose registers are whatever they happened to be when they were captured,
+/// which in the middle of compiled Rust code doesn't mean much, but the stack pointer,
+/// flags, and control registers (e.g. `cr2`, the address of the last page fault) often do.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Registers {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub rsp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cr0: u64,
+    pub cr2: u64,
+    pub cr3: u64,
+    pub cr4: u64,
+}
+
+impl Registers {
+    /// Capture the registers as they are where this is called.
+    #[inline(always)]
+    pub fn capture() -> Registers {
+        let mut registers = Registers::default();
+        unsafe {
+            // The general-purpose registers are stored before anything else can touch them.
+            // (Except the one holding the pointer to `registers`, which is stored as that pointer.)
+            asm!(
+                "mov [{0} + 0x00], rax",
+                "mov [{0} + 0x08], rbx",
+                "mov [{0} + 0x10], rcx",
+                "mov [{0} + 0x18], rdx",
+                "mov [{0} + 0x20], rsi",
+                "mov [{0} + 0x28], rdi",
+                "mov [{0} + 0x30], rbp",
+                "mov [{0} + 0x38], rsp",
+                "mov [{0} + 0x40], r8",
+                "mov [{0} + 0x48], r9",
+                "mov [{0} + 0x50], r10",
+                "mov [{0} + 0x58], r11",
+                "mov [{0} + 0x60], r12",
+                "mov [{0} + 0x68], r13",
+                "mov [{0} + 0x70], r14",
+                "mov [{0} + 0x78], r15",
+                in(reg) &mut registers as *mut Registers,
+            );
+            asm!("lea {}, [rip]", out(reg) registers.rip);
+            asm!("pushfq", "pop {}", out(reg) registers.rflags);
+            asm!("mov {}, cr0", out(reg) registers.cr0);
+            asm!("mov {}, cr2", out(reg) registers.cr2);
+            asm!("mov {}, cr3", out(reg) registers.cr3);
+            asm!("mov {}, cr4", out(reg) registers.cr4);
+        }
+        registers
+    }
+}
+
+impl fmt::Display for Registers {
+    /// Three registers per line, which fits in 80 columns.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers = [
+            ("rax", self.rax), ("rbx", self.rbx), ("rcx", self.rcx),
+            ("rdx", self.rdx), ("rsi", self.rsi), ("rdi", self.rdi),
+            ("rbp", self.rbp), ("rsp", self.rsp), ("r8", self.r8),
+            ("r9", self.r9), ("r10", self.r10), ("r11", self.r11),
+            ("r12", self.r12), ("r13", self.r13), ("r14", self.r14),
+            ("r15", self.r15), ("rip", self.rip), ("rflags", self.rflags),
+            ("cr0", self.cr0), ("cr2", self.cr2), ("cr3", self.cr3),
+            ("cr4", self.cr4),
+        ];
+        for (i, (name, value)) in registers.iter().enumerate() {
+            write!(f, "{:>6} {:016x}", name, value)?;
+            if i % 3 == 2 || i == registers.len() - 1 {
+                writeln!(f)?;
+            } else {
+                write!(f, "  ")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use x86_64::VirtAddr;
+
+// The stack UEFI gives us is somewhere in boot services memory, with nothing underneath it
+// to stop it from quietly running into whatever else the firmware allocated next to it.
+// So as soon as we have our own page tables, we switch to a stack in the kernel's image,
+// with a guard page underneath it which is left unmapped, so that overflowing it faults.
+// This also means we don't need any of boot services memory once we're done setting up.
+
+const GUARD_SIZE: usize = 4096;
+/// UEFI only promises 128 KiB of stack, so twice that is plenty.
+const STACK_SIZE: usize = 256 * 1024;
+
+#[repr(C, align(4096))]
+struct Stack {
+    guard: [u8; GUARD_SIZE],
+    stack: [u8; STACK_SIZE],
+}
+
+static mut KERNEL_STACK: Stack = Stack {
+    guard: [0; GUARD_SIZE],
+    stack: [0; STACK_SIZE],
+};
+
+/// The page underneath the kernel stack, which has to be unmapped for it to do anything.
+pub fn guard_page() -> VirtAddr {
+    unsafe { VirtAddr::from_ptr(&KERNEL_STACK.guard) }
+}
+
+/// Switch to the kernel stack and call `f(arg)` on it.
+///
+/// Nothing ever returns to the old stack, so once `f` has taken whatever it needs out of `arg`,
+/// the old stack can be freed.
+/// This is unsafe because it must only be done once; a second call would pull the stack out from under the first.
+pub unsafe fn switch(f: extern "sysv64" fn(usize) -> !, arg: usize) -> ! {
+    // Stacks grow down, so we start at the end. It's page-aligned, so it's aligned for the call too.
+    let top = VirtAddr::from_ptr(&KERNEL_STACK.stack) + STACK_SIZE as u64;
+    asm!(
+        "mov rsp, {}",
+        "call {}",
+        in(reg) top.as_u64(),
+        in(reg) f,
+        in("rdi") arg,
+        options(noreturn),
+    );
+}
@@ -0,0 +1,62 @@
+// Options the kernel was booted with, like a kernel command line.
+// They're given as the load options of the kernel image, which you can set
+// on the UEFI shell's command line (e.g. `bootproof.efi console.scale=2`)
+// or in the optional data of a UEFI boot entry (e.g. with `efibootmgr --unicode`).
+//
+// Options are separated by whitespace and are written either as `key=value` or just `key`.
+// Options we don't recognize are ignored, since the first word is usually the image's file name.
+
+use uefi::Handle;
+use uefi::proto::loaded_image::LoadedImage;
+use uefi::table::boot::BootServices;
+
+/// The longest command line we'll bother to read.
+const MAX_OPTIONS_LEN: usize = 1024;
+
+#[derive(Copy, Clone, Default)]
+pub struct BootOptions {
+    /// `console.scale=N`: draw the console font `N` times its size,
+    /// rather than picking a scale based on the display resolution.
+    pub console_scale: Option<usize>,
+    /// `debug.screenshot`: once the kernel has finished booting,
+    /// send a screenshot over the serial port (see `screenshot`).
+    pub screenshot: bool,
+}
+
+impl BootOptions {
+    pub fn parse(options: &str) -> BootOptions {
+        let mut boot_options = BootOptions::default();
+        for option in options.split_whitespace() {
+            let (key, value) = match option.find('=') {
+                Some(i) => (&option[..i], &option[i + 1..]),
+                None => (option, ""),
+            };
+
+            match key {
+                "console.scale" => match value.parse() {
+                    Ok(scale) if scale > 0 => boot_options.console_scale = Some(scale),
+                    _ => log::warn!("Ignoring invalid console scale: {}", value),
+                },
+                "debug.screenshot" => boot_options.screenshot = true,
+                _ => {},
+            }
+        }
+        boot_options
+    }
+
+    /// Read the options from the load options of the kernel image.
+    /// If there aren't any (or they can't be read), all of the options have their default values.
+    pub fn from_loaded_image(handle: Handle, bs: &BootServices) -> BootOptions {
+        let loaded_image = match bs.handle_protocol::<LoadedImage>(handle).ok() {
+            Some(loaded_image) => loaded_image.unwrap(),
+            None => return BootOptions::default(),
+        };
+        let loaded_image = unsafe { &*loaded_image.get() };
+
+        let mut buf = [0; MAX_OPTIONS_LEN];
+        match loaded_image.load_options(&mut buf) {
+            Ok(options) => BootOptions::parse(options),
+            Err(_) => BootOptions::default(),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Checksums for detecting corrupt data, e.g. in compressed images or data sent over a serial port.
+// Neither of these is any protection against *deliberate* tampering.
+
+/// The CRC-32 used by zlib, PNG, Ethernet, and most everything else
+/// (polynomial 0x04C11DB7, reflected, with the initial value and result inverted).
+#[derive(Copy, Clone)]
+pub struct Crc32 {
+    state: u32,
+}
+
+/// The CRC of every possible byte, so we can process a byte at a time instead of a bit at a time.
+static CRC32_TABLE: [u32; 256] = crc32_table();
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+impl Crc32 {
+    pub fn new() -> Crc32 {
+        Crc32 { state: 0xFFFFFFFF }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state = CRC32_TABLE[((self.state ^ byte as u32) & 0xFF) as usize] ^ (self.state >> 8);
+        }
+    }
+
+    pub fn finish(&self) -> u32 {
+        !self.state
+    }
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Adler-32, the checksum used by zlib, which is weaker than a CRC but faster to compute.
+pub fn adler32(data: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+    // This is the largest number of bytes we can add up before `b` might overflow,
+    // so we only have to take the remainder once per chunk.
+    const CHUNK_SIZE: usize = 5552;
+
+    let (mut a, mut b) = (1, 0);
+    for chunk in data.chunks(CHUNK_SIZE) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    b << 16 | a
+}
@@ -1,5 +1,9 @@
-use crate::graphics::color::RGB;
+pub mod buffered;
+pub mod framebuffer;
+
+use crate::graphics::color::{self, Color, RGB, RGBA};
 use crate::graphics::font::Glyph;
+use crate::graphics::rect::Rect;
 
 pub trait GraphicDisplay {
     fn resolution(&self) -> (usize, usize);
@@ -14,7 +18,7 @@ pub trait GraphicDisplay {
     /// that the pixel is within the boundaries of the screen.
     unsafe fn set_pixel(&mut self, color: RGB, x: usize, y: usize);
     fn set_pixel_ignore_oob(&mut self, color: RGB, x: usize, y: usize) {
-        if x > self.width() || y > self.height() {
+        if x >= self.width() || y >= self.height() {
             return;
         }
 
@@ -23,9 +27,184 @@ pub trait GraphicDisplay {
         }
     }
 
+    /// Read back the color of a pixel. This may be very slow (e.g. for video memory),
+    /// and if the display can't represent every color exactly, the color may not be quite
+    /// what was drawn.
+    ///
+    /// Unsafe: it is the responsibility of the caller to ensure
+    /// that the pixel is within the boundaries of the screen.
+    unsafe fn get_pixel(&self, x: usize, y: usize) -> RGB;
+
     /// Set the entire display to the same color, clearing everything previously drawn.
     fn clear(&mut self, color: RGB);
 
+    /// Set every pixel in a rectangle to the same color.
+    /// Any part of the rectangle which is outside of the screen is ignored.
+    fn fill_rect(&mut self, color: RGB, rect: Rect) {
+        let rect = rect.intersection(&Rect::new(0, 0, self.width(), self.height()));
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                unsafe {
+                    self.set_pixel(color, x, y);
+                }
+            }
+        }
+    }
+
+    /// Draw the one-pixel-wide outline of a rectangle.
+    /// Any part of the rectangle which is outside of the screen is ignored.
+    fn draw_rect(&mut self, color: RGB, rect: Rect) {
+        if rect.is_empty() {
+            return;
+        }
+
+        self.fill_rect(color, Rect::new(rect.x, rect.y, rect.width, 1));
+        self.fill_rect(color, Rect::new(rect.x, rect.bottom() - 1, rect.width, 1));
+        self.fill_rect(color, Rect::new(rect.x, rect.y, 1, rect.height));
+        self.fill_rect(color, Rect::new(rect.right() - 1, rect.y, 1, rect.height));
+    }
+
+    /// Draw a one-pixel-wide line between two points, including both end points.
+    /// The points may be off of the screen (even at negative coordinates),
+    /// in which case only the part of the line which is on the screen is drawn.
+    fn draw_line(&mut self, color: RGB, from: (isize, isize), to: (isize, isize)) {
+        let (width, height) = self.resolution();
+
+        // Horizontal and vertical lines are just thin rectangles,
+        // which most displays can fill much faster than going pixel by pixel.
+        if from.0 == to.0 || from.1 == to.1 {
+            if let (Some((left, right)), Some((top, bottom))) =
+                (clip_span(from.0, to.0, width), clip_span(from.1, to.1, height)) {
+                self.fill_rect(color, Rect::new(left, top, right - left + 1, bottom - top + 1));
+            }
+            return;
+        }
+
+        // We step along whichever axis the line is longer in, one pixel at a time,
+        // and work out where the line is along the other axis at each step, rounding to the nearest pixel.
+        // Only the steps which are on the screen are taken, so a line that goes far off of the screen
+        // takes no longer to draw than one that doesn't, and the arithmetic is done in 128 bits,
+        // because the distance between two points can be too big for an `isize`.
+        let distance = |a: isize, b: isize| (b as i128 - a as i128).unsigned_abs();
+        let steep = distance(from.1, to.1) > distance(from.0, to.0);
+        // From here on, coordinates and the size of the screen are along the long axis, then the short one.
+        let (from, to, size) = if steep {
+            ((from.1, from.0), (to.1, to.0), (height, width))
+        } else {
+            (from, to, (width, height))
+        };
+        let (long, short) = (distance(from.0, to.0), distance(from.1, to.1));
+
+        let (start, end) = match clip_span(from.0, to.0, size.0) {
+            Some(span) => span,
+            None => return,
+        };
+        for a in start..=end {
+            let offset = (distance(from.0, a as isize) * short + long / 2) / long;
+            let b = if to.1 > from.1 { from.1 as i128 + offset as i128 } else { from.1 as i128 - offset as i128 };
+            if b < 0 || b >= size.1 as i128 {
+                continue;
+            }
+
+            let (x, y) = if steep { (b as usize, a) } else { (a, b as usize) };
+            unsafe {
+                self.set_pixel(color, x, y);
+            }
+        }
+    }
+
+    /// Draw part of a buffer of pixels, with its top-left corner at (`x`, `y`).
+    /// `pixels` is stored row by row, with `stride` pixels per row,
+    /// and `src` is the part of it to draw.
+    /// Any part which would be outside of the screen (even at negative coordinates) is ignored.
+    ///
+    /// Panics if `src` isn't entirely within `pixels`.
+    fn blit(&mut self, pixels: &[RGB], stride: usize, src: Rect, x: isize, y: isize) {
+        let (src, x, y) = match clip_blit(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        for row in 0..src.height {
+            let start = (src.y + row) * stride + src.x;
+            for (col, &pixel) in pixels[start..start + src.width].iter().enumerate() {
+                unsafe {
+                    self.set_pixel(pixel, x + col, y + row);
+                }
+            }
+        }
+    }
+
+    /// Like `blit`, but the pixels are drawn on top of what's already on the display,
+    /// according to their alpha. Wherever the pixels are translucent,
+    /// this has to read back the display, which can be slow (see `get_pixel`).
+    ///
+    /// Panics if `src` isn't entirely within `pixels`.
+    fn blit_blended(&mut self, pixels: &[RGBA], stride: usize, src: Rect, x: isize, y: isize) {
+        let (src, x, y) = match clip_blit(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        for row in 0..src.height {
+            let start = (src.y + row) * stride + src.x;
+            for (col, &pixel) in pixels[start..start + src.width].iter().enumerate() {
+                let (x, y) = (x + col, y + row);
+                unsafe {
+                    match pixel.a() {
+                        0 => {},
+                        0xFF => self.set_pixel(pixel.into_rgb(), x, y),
+                        _ => {
+                            let bg = self.get_pixel(x, y);
+                            self.set_pixel(pixel.over(bg), x, y);
+                        },
+                    }
+                }
+            }
+        }
+    }
+
+    /// Copy a rectangle of the screen so that its top-left corner is at (`x`, `y`).
+    /// The source and destination may overlap.
+    /// Any part of either rectangle which is outside of the screen is ignored.
+    fn copy_rect(&mut self, src: Rect, x: usize, y: usize) {
+        let (src, x, y) = match clip_copy(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        // If the rectangles overlap, we have to copy each pixel before we overwrite it,
+        // so we copy starting from the side the destination is moving towards.
+        for i in 0..src.height {
+            let row = if y > src.y { src.height - 1 - i } else { i };
+            for j in 0..src.width {
+                let col = if x > src.x { src.width - 1 - j } else { j };
+                unsafe {
+                    let pixel = self.get_pixel(src.x + col, src.y + row);
+                    self.set_pixel(pixel, x + col, y + row);
+                }
+            }
+        }
+    }
+
+    /// Move everything within a rectangle up by `lines` pixels,
+    /// filling in the space at the bottom with `fill`. Nothing outside of the rectangle changes.
+    fn scroll_up(&mut self, rect: Rect, lines: usize, fill: RGB) {
+        let rect = rect.intersection(&Rect::new(0, 0, self.width(), self.height()));
+        let lines = lines.min(rect.height);
+        self.copy_rect(Rect::new(rect.x, rect.y + lines, rect.width, rect.height - lines), rect.x, rect.y);
+        self.fill_rect(fill, Rect::new(rect.x, rect.bottom() - lines, rect.width, lines));
+    }
+
+    /// Move everything within a rectangle down by `lines` pixels,
+    /// filling in the space at the top with `fill`. Nothing outside of the rectangle changes.
+    fn scroll_down(&mut self, rect: Rect, lines: usize, fill: RGB) {
+        let rect = rect.intersection(&Rect::new(0, 0, self.width(), self.height()));
+        let lines = lines.min(rect.height);
+        self.copy_rect(Rect::new(rect.x, rect.y, rect.width, rect.height - lines), rect.x, rect.y + lines);
+        self.fill_rect(fill, Rect::new(rect.x, rect.y, rect.width, lines));
+    }
+
     /// Display everything that was drawn to the screen.
     fn refresh(&mut self);
 
@@ -41,6 +220,11 @@ pub trait GraphicDisplay {
         //   1. If it is going to be out-of-bounds and is inside the bounding box, panic, and
         //   2. if it is outside of the bounding box, don't bother trying to draw that row.
 
+        // The bounding box starts where the glyph would be if it had no offset.
+        let (offset_x, offset_y) = glyph.offset();
+        let (x, y) = (x + offset_x, y + offset_y);
+        let bounding_box = (bounding_box.0.saturating_sub(offset_x), bounding_box.1.saturating_sub(offset_y));
+
         for glyph_x in 0..glyph.width().min(bounding_box.0) {
             for glyph_y in 0..glyph.height().min(bounding_box.1) {
                 if glyph.get(glyph_x, glyph_y) {
@@ -59,4 +243,98 @@ pub trait GraphicDisplay {
             }
         }
     }
+
+    /// Like `draw_glyph`, but pixels which are only partly covered by the glyph
+    /// are blended between `color` and `bg` (see `Glyph::coverage`), for anti-aliased glyphs.
+    /// `bg` should be the color behind the glyph;
+    /// we don't read it back from the display because that's very slow for video memory.
+    ///
+    /// Unlike `draw_glyph`, nothing outside of the bounding box is drawn, not even to the right of it:
+    /// whatever stuck out would be drawn over the next cell, which wouldn't know to redraw itself,
+    /// and `bg` isn't necessarily the color behind it anyway.
+    ///
+    /// Unsafe: it is the responsibility of the caller to ensure
+    /// that the entire glyph fits within the boundaries of the screen.
+    unsafe fn draw_glyph_blended(&mut self, bounding_box: (usize, usize), x: usize, y: usize,
+                                 color: RGB, bg: RGB, glyph: &dyn Glyph) {
+        let rect = clip_glyph(self.resolution(), bounding_box, x, y, glyph, false);
+        for glyph_x in 0..rect.width {
+            for glyph_y in 0..rect.height {
+                let coverage = glyph.coverage(glyph_x, glyph_y);
+                if coverage != 0 {
+                    self.set_pixel(color::blend(bg, color, coverage), rect.x + glyph_x, rect.y + glyph_y);
+                }
+            }
+        }
+    }
+}
+
+/// Clip a glyph drawn in a bounding box with its top left corner at (`x`, `y`) to the screen.
+/// Returns where the glyph goes on the screen, once its offset is applied,
+/// and how much of it to draw: whatever is inside the bounding box and on the screen,
+/// and if `overhang` is set, whatever sticks out to the right of the bounding box but is still on the screen.
+fn clip_glyph(resolution: (usize, usize), bounding_box: (usize, usize), x: usize, y: usize,
+              glyph: &dyn Glyph, overhang: bool) -> Rect {
+    let (width, height) = resolution;
+    // The bounding box starts where the glyph would be if it had no offset.
+    let (offset_x, offset_y) = glyph.offset();
+    let (x, y) = (x + offset_x, y + offset_y);
+    let bounding_box = (bounding_box.0.saturating_sub(offset_x), bounding_box.1.saturating_sub(offset_y));
+    let glyph_width = if overhang { glyph.width() } else { glyph.width().min(bounding_box.0) };
+    Rect::new(
+        x,
+        y,
+        glyph_width.min(width.saturating_sub(x)),
+        glyph.height().min(bounding_box.1).min(height.saturating_sub(y)),
+    )
+}
+
+/// Clip the span of pixels from `a` to `b` (inclusive, in either order) to a screen `size` pixels long.
+/// Returns the first and last pixel of the span which are on the screen, or `None` if none of them are.
+fn clip_span(a: isize, b: isize, size: usize) -> Option<(usize, usize)> {
+    let (start, end) = (a.min(b), a.max(b));
+    if end < 0 || start.max(0) as usize >= size {
+        return None;
+    }
+    Some((start.max(0) as usize, (end as usize).min(size - 1)))
+}
+
+/// Clip a blit to a screen of the given resolution.
+/// Returns the part of the source rectangle which is visible and where it goes on the screen,
+/// or `None` if none of it is visible.
+fn clip_blit(resolution: (usize, usize), src: Rect, x: isize, y: isize) -> Option<(Rect, usize, usize)> {
+    let (width, height) = resolution;
+    // Whatever is above or to the left of the screen is cut off of the source rectangle.
+    // (`unsigned_abs` because negating `isize::MIN` overflows.)
+    let skip = |position: isize| if position < 0 { position.unsigned_abs() } else { 0 };
+    let (skip_x, skip_y) = (skip(x), skip(y));
+    let (x, y) = (x.max(0) as usize, y.max(0) as usize);
+    // Checking this before moving the source rectangle means it can't move past the end of itself.
+    if x >= width || y >= height || skip_x >= src.width || skip_y >= src.height {
+        return None;
+    }
+
+    let src = Rect::new(
+        src.x + skip_x,
+        src.y + skip_y,
+        (src.width - skip_x).min(width - x),
+        (src.height - skip_y).min(height - y),
+    );
+    Some((src, x, y))
+}
+
+/// Clip a copy from one part of a screen of the given resolution to another.
+/// Returns the part of the source rectangle which is on the screen and can be copied
+/// to somewhere on the screen, and where it goes, or `None` if there is no such part.
+fn clip_copy(resolution: (usize, usize), src: Rect, x: usize, y: usize) -> Option<(Rect, usize, usize)> {
+    let (width, height) = resolution;
+    let clipped = src.intersection(&Rect::new(0, 0, width, height));
+    // If the top or left of the source was cut off, the destination moves to match.
+    let (x, y) = (x + (clipped.x - src.x), y + (clipped.y - src.y));
+    if clipped.is_empty() || x >= width || y >= height {
+        return None;
+    }
+
+    let src = Rect::new(clipped.x, clipped.y, clipped.width.min(width - x), clipped.height.min(height - y));
+    Some((src, x, y))
 }
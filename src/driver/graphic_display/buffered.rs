@@ -0,0 +1,236 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use crate::driver::graphic_display::{self, GraphicDisplay};
+use crate::graphics::color::{self, Color, RGB, RGBA};
+use crate::graphics::font::Glyph;
+use crate::graphics::rect::Rect;
+
+/// Past this many separate dirty rectangles, we give up on tracking them individually
+/// and merge them all into one big rectangle.
+/// Scanning a long list on every `set_pixel` would cost more than it could save.
+const MAX_DIRTY_RECTS: usize = 32;
+
+/// A double-buffered wrapper around another graphic display.
+///
+/// Everything is drawn to a back buffer in ordinary memory,
+/// and only the regions which have changed since the last `refresh`
+/// are copied to the underlying (front) display.
+/// This prevents flickering, and is much faster than drawing everything to
+/// video memory, which is slow to write to and *extremely* slow to read from.
+pub struct BufferedDisplay<D: GraphicDisplay> {
+    front: D,
+    back: Box<[RGB]>,
+    dirty: Vec<Rect>,
+}
+
+impl<D: GraphicDisplay> BufferedDisplay<D> {
+    /// The back buffer starts out black, so the front display will be cleared
+    /// on the first refresh.
+    pub fn new(front: D) -> BufferedDisplay<D> {
+        let (width, height) = front.resolution();
+        let mut back = Vec::new();
+        back.resize(width * height, RGB::new(0, 0, 0));
+
+        BufferedDisplay {
+            front: front,
+            back: back.into_boxed_slice(),
+            dirty: {
+                let mut vec = Vec::with_capacity(MAX_DIRTY_RECTS);
+                vec.push(Rect::new(0, 0, width, height));
+                vec
+            },
+        }
+    }
+
+    pub fn front(&self) -> &D {
+        &self.front
+    }
+
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width(), self.height())
+    }
+
+    fn index(&self, x: usize, y: usize) -> usize {
+        self.width() * y + x
+    }
+
+    /// Record that a region of the back buffer has changed
+    /// and must be copied to the front buffer on the next refresh.
+    pub fn damage(&mut self, rect: Rect) {
+        let mut rect = rect.intersection(&self.bounds());
+        if rect.is_empty() {
+            return;
+        }
+
+        // Consecutive draws usually land in the same area,
+        // so the most recent rectangle is the most likely to already cover this one.
+        if self.dirty.iter().rev().any(|r| r.contains(&rect)) {
+            return;
+        }
+
+        // Absorb every rectangle this one touches. Growing the rectangle
+        // may make it touch rectangles which we've already passed over,
+        // so we keep going until nothing changes.
+        loop {
+            let before = rect;
+            let mut i = 0;
+            while i < self.dirty.len() {
+                if self.dirty[i].touches(&rect) {
+                    rect = rect.union(&self.dirty.swap_remove(i));
+                } else {
+                    i += 1;
+                }
+            }
+
+            if rect == before {
+                break;
+            }
+        }
+
+        if self.dirty.len() >= MAX_DIRTY_RECTS {
+            rect = self.dirty.drain(..).fold(rect, |acc, r| acc.union(&r));
+        }
+
+        self.dirty.push(rect);
+    }
+}
+
+impl<D: GraphicDisplay> GraphicDisplay for BufferedDisplay<D> {
+    fn resolution(&self) -> (usize, usize) {
+        self.front.resolution()
+    }
+
+    unsafe fn set_pixel(&mut self, color: RGB, x: usize, y: usize) {
+        let i = self.index(x, y);
+        self.back[i] = color;
+        self.damage(Rect::new(x, y, 1, 1));
+    }
+
+    unsafe fn get_pixel(&self, x: usize, y: usize) -> RGB {
+        self.back[self.index(x, y)]
+    }
+
+    fn clear(&mut self, color: RGB) {
+        for pixel in self.back.iter_mut() {
+            *pixel = color;
+        }
+
+        self.dirty.clear();
+        self.dirty.push(self.bounds());
+    }
+
+    fn fill_rect(&mut self, color: RGB, rect: Rect) {
+        let rect = rect.intersection(&self.bounds());
+        for y in rect.y..rect.bottom() {
+            let row = self.index(0, y);
+            for pixel in &mut self.back[row + rect.x..row + rect.right()] {
+                *pixel = color;
+            }
+        }
+
+        self.damage(rect);
+    }
+
+    fn blit(&mut self, pixels: &[RGB], stride: usize, src: Rect, x: isize, y: isize) {
+        let (src, x, y) = match graphic_display::clip_blit(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        for row in 0..src.height {
+            let start = (src.y + row) * stride + src.x;
+            let dest = self.index(x, y + row);
+            self.back[dest..dest + src.width].copy_from_slice(&pixels[start..start + src.width]);
+        }
+
+        self.damage(Rect::new(x, y, src.width, src.height));
+    }
+
+    fn blit_blended(&mut self, pixels: &[RGBA], stride: usize, src: Rect, x: isize, y: isize) {
+        let (src, x, y) = match graphic_display::clip_blit(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        // Reading back the back buffer is cheap, unlike video memory.
+        for row in 0..src.height {
+            let start = (src.y + row) * stride + src.x;
+            let dest = self.index(x, y + row);
+            let dest = &mut self.back[dest..dest + src.width];
+            for (bg, &pixel) in dest.iter_mut().zip(&pixels[start..start + src.width]) {
+                match pixel.a() {
+                    0 => {},
+                    0xFF => *bg = pixel.into_rgb(),
+                    _ => *bg = pixel.over(*bg),
+                }
+            }
+        }
+
+        self.damage(Rect::new(x, y, src.width, src.height));
+    }
+
+    fn copy_rect(&mut self, src: Rect, x: usize, y: usize) {
+        let (src, x, y) = match graphic_display::clip_copy(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        // `copy_within` handles a row overlapping itself,
+        // but we still have to pick the order of the rows so we don't overwrite rows before we copy them.
+        for i in 0..src.height {
+            let row = if y > src.y { src.height - 1 - i } else { i };
+            let start = self.index(src.x, src.y + row);
+            let dest = self.index(x, y + row);
+            self.back.copy_within(start..start + src.width, dest);
+        }
+
+        self.damage(Rect::new(x, y, src.width, src.height));
+    }
+
+    fn refresh(&mut self) {
+        // Each dirty rectangle is copied to the front display with one blit,
+        // so the front display can use whatever fast path it has for copying pixels.
+        let width = self.width();
+        for rect in self.dirty.drain(..) {
+            self.front.blit(&self.back, width, rect, rect.x as isize, rect.y as isize);
+        }
+
+        self.front.refresh();
+    }
+
+    unsafe fn draw_glyph(&mut self, bounding_box: (usize, usize), x: usize, y: usize, color: RGB, glyph: &dyn Glyph) {
+        // Like the default implementation, we draw the parts of the glyph
+        // which stick out horizontally past the bounding box only if they're on the screen,
+        // and nothing which sticks out vertically.
+        let rect = graphic_display::clip_glyph(self.resolution(), bounding_box, x, y, glyph, true);
+
+        // Marking the glyph's damage all at once is much cheaper than going pixel by pixel.
+        self.damage(rect);
+        for glyph_y in 0..rect.height {
+            for glyph_x in 0..rect.width {
+                if glyph.get(glyph_x, glyph_y) {
+                    let i = self.index(rect.x + glyph_x, rect.y + glyph_y);
+                    self.back[i] = color;
+                }
+            }
+        }
+    }
+
+    unsafe fn draw_glyph_blended(&mut self, bounding_box: (usize, usize), x: usize, y: usize,
+                                 color: RGB, bg: RGB, glyph: &dyn Glyph) {
+        // This is the same as `draw_glyph`, except for the blending,
+        // and that nothing outside of the bounding box is drawn (see the default implementation).
+        let rect = graphic_display::clip_glyph(self.resolution(), bounding_box, x, y, glyph, false);
+
+        self.damage(rect);
+        for glyph_y in 0..rect.height {
+            for glyph_x in 0..rect.width {
+                let coverage = glyph.coverage(glyph_x, glyph_y);
+                if coverage != 0 {
+                    let i = self.index(rect.x + glyph_x, rect.y + glyph_y);
+                    self.back[i] = color::blend(bg, color, coverage);
+                }
+            }
+        }
+    }
+}
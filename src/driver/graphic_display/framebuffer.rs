@@ -0,0 +1,231 @@
+use crate::driver::graphic_display::{self, GraphicDisplay};
+use crate::graphics::color::{NativePixel, PixelFormat, RGB};
+use crate::graphics::rect::Rect;
+use uefi::proto::console::gop::{self, GraphicsOutput};
+use uefi::table::boot::BootServices;
+
+/// Everything we need to know to draw to a linear framebuffer.
+///
+/// The Graphics Output Protocol is a boot service, so it goes away when we exit boot services.
+/// However, the framebuffer it describes stays exactly where it was,
+/// so we copy out the current mode before exiting and keep drawing to that memory afterwards.
+#[derive(Copy, Clone)]
+pub struct FramebufferInfo {
+    base: *mut u8,
+    size: usize,
+    resolution: (usize, usize),
+    /// The number of *pixels* (not bytes) from the start of one row to the start of the next.
+    /// This may be larger than the width of the display.
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl FramebufferInfo {
+    /// Capture the current video mode of the UEFI Graphics Output Protocol.
+    ///
+    /// Returns `None` if there is no GOP (e.g. on a headless machine)
+    /// or if it doesn't provide a framebuffer at all.
+    pub fn from_gop(bs: &BootServices) -> Option<FramebufferInfo> {
+        let gop = bs.locate_protocol::<GraphicsOutput>().ok()?.unwrap();
+        let gop = unsafe { &mut *gop.get() };
+
+        let mode = gop.current_mode_info();
+        let format = match mode.pixel_format() {
+            gop::PixelFormat::RGB => PixelFormat::Rgb,
+            gop::PixelFormat::BGR => PixelFormat::Bgr,
+            gop::PixelFormat::Bitmask => {
+                let mask = mode.pixel_bitmask()?;
+                PixelFormat::Bitmask {
+                    red: mask.red,
+                    green: mask.green,
+                    blue: mask.blue,
+                    reserved: mask.reserved,
+                }
+            },
+            // `BltOnly` means there's no framebuffer, and the only way to draw
+            // is through the GOP's blit function, which won't exist after boot services.
+            gop::PixelFormat::BltOnly => return None,
+        };
+
+        let mut fb = gop.frame_buffer();
+        Some(FramebufferInfo {
+            base: fb.as_mut_ptr(),
+            size: fb.size(),
+            resolution: mode.resolution(),
+            stride: mode.stride(),
+            format: format,
+        })
+    }
+
+    /// The physical address of the framebuffer.
+    /// It's outside of the memory map, so the page tables have to map it specially.
+    pub fn base(&self) -> u64 {
+        self.base as u64
+    }
+
+    /// The size of the framebuffer in bytes.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// A graphic display which draws directly to a linear framebuffer in video memory.
+pub struct Framebuffer {
+    info: FramebufferInfo,
+    bytes_per_pixel: usize,
+    /// The most recently encoded color.
+    /// Nearly every run of `set_pixel` calls (glyphs, fills, etc.) uses a single color,
+    /// so this saves us from encoding the same color over and over.
+    last_color: (RGB, NativePixel),
+}
+
+impl Framebuffer {
+    /// Unsafe: it is the responsibility of the caller to ensure that the framebuffer
+    /// described by `info` is still mapped and that nothing else is drawing to it.
+    pub unsafe fn new(info: FramebufferInfo) -> Framebuffer {
+        let black = RGB::new(0, 0, 0);
+        Framebuffer {
+            info: info,
+            bytes_per_pixel: info.format.bytes_per_pixel(),
+            last_color: (black, info.format.encode(black)),
+        }
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.info.format
+    }
+
+    fn encode(&mut self, color: RGB) -> NativePixel {
+        if self.last_color.0 != color {
+            self.last_color = (color, self.info.format.encode(color));
+        }
+        self.last_color.1
+    }
+
+    /// Unsafe: it is the responsibility of the caller to ensure
+    /// that the pixel is within the boundaries of the screen.
+    pub unsafe fn set_native_pixel(&mut self, pixel: NativePixel, x: usize, y: usize) {
+        let offset = (y * self.info.stride + x) * self.bytes_per_pixel;
+        debug_assert!(offset + self.bytes_per_pixel <= self.info.size);
+        let ptr = self.info.base.add(offset);
+        // Video memory isn't ordinary memory, so we don't want the compiler
+        // to get clever and elide or reorder our writes.
+        // Three-byte pixels aren't aligned, so they have to be written a byte at a time.
+        match self.bytes_per_pixel {
+            4 => core::ptr::write_volatile(ptr as *mut u32, pixel.0),
+            2 => core::ptr::write_volatile(ptr as *mut u16, pixel.0 as u16),
+            _ => {
+                let bytes = pixel.0.to_le_bytes();
+                for i in 0..self.bytes_per_pixel {
+                    core::ptr::write_volatile(ptr.add(i), bytes[i]);
+                }
+            },
+        }
+    }
+
+    /// Unsafe: it is the responsibility of the caller to ensure
+    /// that the pixel is within the boundaries of the screen.
+    pub unsafe fn get_native_pixel(&self, x: usize, y: usize) -> NativePixel {
+        let offset = (y * self.info.stride + x) * self.bytes_per_pixel;
+        debug_assert!(offset + self.bytes_per_pixel <= self.info.size);
+        let ptr = self.info.base.add(offset);
+        NativePixel(match self.bytes_per_pixel {
+            4 => core::ptr::read_volatile(ptr as *const u32),
+            2 => core::ptr::read_volatile(ptr as *const u16) as u32,
+            _ => {
+                let mut bytes = [0; 4];
+                for i in 0..self.bytes_per_pixel {
+                    bytes[i] = core::ptr::read_volatile(ptr.add(i));
+                }
+                u32::from_le_bytes(bytes)
+            },
+        })
+    }
+
+    /// A pointer to the first byte of a pixel.
+    fn pixel_ptr(&self, x: usize, y: usize) -> *mut u8 {
+        let offset = (y * self.info.stride + x) * self.bytes_per_pixel;
+        self.info.base.wrapping_add(offset)
+    }
+}
+
+impl GraphicDisplay for Framebuffer {
+    fn resolution(&self) -> (usize, usize) {
+        self.info.resolution
+    }
+
+    unsafe fn set_pixel(&mut self, color: RGB, x: usize, y: usize) {
+        let pixel = self.encode(color);
+        self.set_native_pixel(pixel, x, y);
+    }
+
+    unsafe fn get_pixel(&self, x: usize, y: usize) -> RGB {
+        self.info.format.decode(self.get_native_pixel(x, y))
+    }
+
+    fn clear(&mut self, color: RGB) {
+        let pixel = self.encode(color);
+        for y in 0..self.height() {
+            for x in 0..self.width() {
+                unsafe {
+                    self.set_native_pixel(pixel, x, y);
+                }
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, color: RGB, rect: Rect) {
+        let pixel = self.encode(color);
+        let rect = rect.intersection(&Rect::new(0, 0, self.width(), self.height()));
+        for y in rect.y..rect.bottom() {
+            for x in rect.x..rect.right() {
+                unsafe {
+                    self.set_native_pixel(pixel, x, y);
+                }
+            }
+        }
+    }
+
+    fn blit(&mut self, pixels: &[RGB], stride: usize, src: Rect, x: isize, y: isize) {
+        let (src, x, y) = match graphic_display::clip_blit(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        // Unlike the default implementation, this skips the bounds checks on every pixel,
+        // and pixels of the same color in a row (which are common in pictures) are only encoded once.
+        for row in 0..src.height {
+            let start = (src.y + row) * stride + src.x;
+            for (col, &color) in pixels[start..start + src.width].iter().enumerate() {
+                let pixel = self.encode(color);
+                unsafe {
+                    self.set_native_pixel(pixel, x + col, y + row);
+                }
+            }
+        }
+    }
+
+    fn copy_rect(&mut self, src: Rect, x: usize, y: usize) {
+        let (src, x, y) = match graphic_display::clip_copy(self.resolution(), src, x, y) {
+            Some(clipped) => clipped,
+            None => return,
+        };
+
+        // Rows are contiguous in memory, so we can copy a whole row at a time
+        // (`ptr::copy` handles rows which overlap) instead of decoding and re-encoding every pixel.
+        // Reading from video memory is slow, but reading it in big blocks is much less slow.
+        let row_size = src.width * self.bytes_per_pixel;
+        for i in 0..src.height {
+            // If the destination is lower than the source, we copy the bottom rows first
+            // so we don't overwrite rows before we copy them.
+            let row = if y > src.y { src.height - 1 - i } else { i };
+            unsafe {
+                core::ptr::copy(self.pixel_ptr(src.x, src.y + row), self.pixel_ptr(x, y + row), row_size);
+            }
+        }
+    }
+
+    fn refresh(&mut self) {
+        // Everything is drawn directly to video memory, so it's already on the screen.
+    }
+}
@@ -1,34 +1,177 @@
 pub mod graphic;
 
 use alloc::boxed::Box;
+use core::ops::BitOr;
+use crate::driver::graphic_display::GraphicDisplay;
+use crate::graphics::color::{Color, RGB};
+use crate::unicode;
 
 /// A text-mode display. Basically, an array of characters that you can set in any order.
 pub trait TextDisplay {
     fn borrow_frame<'a>(&'a self) -> &'a TextDisplayFrame;
     fn borrow_mut_frame<'a>(&'a mut self) -> &'a mut TextDisplayFrame;
     /// Display all changes made to the frame.
+    /// Cells which haven't changed since the last refresh may not be redrawn.
     fn refresh(&mut self);
+
+    /// The graphic display this is drawn on, if it's drawn on one (e.g. so we can take screenshots).
+    fn graphic_display(&self) -> Option<&dyn GraphicDisplay> {
+        None
+    }
+}
+
+/// Ways to display a character other than its colors, which may be combined.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Attributes(u8);
+
+impl Attributes {
+    pub const NONE: Attributes = Attributes(0);
+    pub const BOLD: Attributes = Attributes(1 << 0);
+    pub const UNDERLINE: Attributes = Attributes(1 << 1);
+    /// Swap the foreground and background colors.
+    pub const INVERSE: Attributes = Attributes(1 << 2);
+    /// Draw the foreground at reduced intensity.
+    pub const DIM: Attributes = Attributes(1 << 3);
+
+    pub fn contains(self, other: Attributes) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Attributes) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Attributes) {
+        self.0 &= !other.0;
+    }
+
+    pub fn toggle(&mut self, other: Attributes) {
+        self.0 ^= other.0;
+    }
+}
+
+impl BitOr for Attributes {
+    type Output = Attributes;
+
+    fn bitor(self, other: Attributes) -> Attributes {
+        Attributes(self.0 | other.0)
+    }
+}
+
+/// How the character in a cell should be displayed.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Style {
+    pub fg: RGB,
+    pub bg: RGB,
+    pub attrs: Attributes,
+}
+
+impl Style {
+    pub fn new(fg: impl Color, bg: impl Color) -> Style {
+        Style {
+            fg: fg.into_rgb(),
+            bg: bg.into_rgb(),
+            attrs: Attributes::NONE,
+        }
+    }
+}
+
+/// The number of combining marks (like accents) which can be drawn over a single character.
+/// Any more than that are thrown away.
+pub const MAX_MARKS: usize = 2;
+
+/// A single character of a text display along with how to display it.
+/// A null character represents an empty cell, which still has a background color.
+///
+/// Wide characters (see `unicode::width`) take up two cells:
+/// the character goes in the first cell, and the second cell is a continuation cell,
+/// which is covered by the first cell and isn't drawn by itself.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,
+    /// Combining marks to draw over the character, in order. Unused slots are null.
+    pub marks: [char; MAX_MARKS],
+    pub style: Style,
+}
+
+impl Cell {
+    /// The character in a continuation cell. It's a noncharacter,
+    /// so it can't be confused with anything that a program would actually want to display.
+    pub const CONTINUATION: char = '\u{10FFFF}';
+
+    pub fn new(ch: char, style: Style) -> Cell {
+        Cell { ch: ch, marks: ['\u{0}'; MAX_MARKS], style: style }
+    }
+
+    /// An empty cell.
+    pub fn blank(style: Style) -> Cell {
+        Cell::new('\u{0}', style)
+    }
+
+    /// The second half of a wide character.
+    pub fn continuation(style: Style) -> Cell {
+        Cell::new(Cell::CONTINUATION, style)
+    }
+
+    pub fn is_continuation(&self) -> bool {
+        self.ch == Cell::CONTINUATION
+    }
+
+    /// Whether this cell holds a wide character, i.e. it's followed by a continuation cell.
+    pub fn is_wide(&self) -> bool {
+        unicode::width(self.ch) == 2
+    }
+
+    /// Add a combining mark to be drawn over this cell's character.
+    /// Returns false if there wasn't any room left for it.
+    pub fn push_mark(&mut self, mark: char) -> bool {
+        match self.marks.iter_mut().find(|m| **m == '\u{0}') {
+            Some(slot) => {
+                *slot = mark;
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The combining marks on this cell.
+    pub fn marks(&self) -> impl Iterator<Item = char> + '_ {
+        self.marks.iter().copied().take_while(|&m| m != '\u{0}')
+    }
 }
 
 /// A frame of a text display; basically a 2d array of characters which you can set how you please.
 /// However, this frame doesn't know anything about how to display itself;
 /// that's what the TextDisplay trait is for.
+///
+/// The frame keeps track of which cells have changed since they were last displayed,
+/// so that text displays only have to redraw what actually changed.
 pub struct TextDisplayFrame {
     resolution: (usize, usize),
-    buf: Box<[char]>,
+    /// The style of empty cells, used when the frame is cleared.
+    default_style: Style,
+    buf: Box<[Cell]>,
+    dirty: Box<[bool]>,
+    cursor: Option<(usize, usize)>,
 }
 
 impl TextDisplayFrame {
-    pub fn new(resolution: (usize, usize)) -> TextDisplayFrame {
+    pub fn new(resolution: (usize, usize), default_style: Style) -> TextDisplayFrame {
         use alloc::vec::Vec;
 
         let (width, height) = resolution;
         let mut buf = Vec::new();
-        buf.resize(width * height, '\u{0}');
+        buf.resize(width * height, Cell::blank(default_style));
+        // Nothing has been displayed yet, so every cell starts out dirty.
+        let mut dirty = Vec::new();
+        dirty.resize(width * height, true);
 
         TextDisplayFrame {
             resolution: resolution,
-            buf: buf.into_boxed_slice()
+            default_style: default_style,
+            buf: buf.into_boxed_slice(),
+            dirty: dirty.into_boxed_slice(),
+            cursor: None,
         }
     }
 
@@ -44,10 +187,18 @@ impl TextDisplayFrame {
         self.resolution.1
     }
 
-    /// Set all characters in this frame to null.
+    pub fn default_style(&self) -> Style {
+        self.default_style
+    }
+
+    /// Set all cells in this frame to empty cells in the default style.
     pub fn clear(&mut self) {
+        let blank = Cell::blank(self.default_style);
         for i in 0..self.buf.len() {
-            self.buf[i] = '\u{0}';
+            if self.buf[i] != blank {
+                self.buf[i] = blank;
+                self.dirty[i] = true;
+            }
         }
     }
 
@@ -55,12 +206,53 @@ impl TextDisplayFrame {
         self.width() * y + x
     }
 
-    pub fn get(&self, x: usize, y: usize) -> char {
+    pub fn get(&self, x: usize, y: usize) -> Cell {
         self.buf[self.index(x, y)]
     }
 
-    pub fn set(&mut self, x: usize, y: usize, c: char) {
+    pub fn set(&mut self, x: usize, y: usize, cell: Cell) {
         let i = self.index(x, y);
-        self.buf[i] = c;
+        if self.buf[i] != cell {
+            self.buf[i] = cell;
+            self.dirty[i] = true;
+        }
+    }
+
+    /// The cell the cursor is displayed in, if it's displayed at all.
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        self.cursor
+    }
+
+    /// Move or hide the cursor. Both the old and new cursor cells will be redrawn.
+    pub fn set_cursor(&mut self, cursor: Option<(usize, usize)>) {
+        if self.cursor == cursor {
+            return;
+        }
+
+        if let Some((x, y)) = self.cursor {
+            self.mark_dirty(x, y);
+        }
+        if let Some((x, y)) = cursor {
+            self.mark_dirty(x, y);
+        }
+        self.cursor = cursor;
+    }
+
+    /// Whether the cell has changed since the frame was last marked clean.
+    pub fn is_dirty(&self, x: usize, y: usize) -> bool {
+        self.dirty[self.index(x, y)]
+    }
+
+    /// Force a cell to be redrawn even though it hasn't changed.
+    pub fn mark_dirty(&mut self, x: usize, y: usize) {
+        let i = self.index(x, y);
+        self.dirty[i] = true;
+    }
+
+    /// Record that every cell has been displayed.
+    pub fn mark_clean(&mut self) {
+        for dirty in self.dirty.iter_mut() {
+            *dirty = false;
+        }
     }
 }
@@ -1,38 +1,197 @@
 use crate::driver::graphic_display::GraphicDisplay;
-use crate::driver::text_display::{TextDisplayFrame, TextDisplay};
-use crate::graphics::color::{Color, RGB};
+use crate::driver::text_display::{Attributes, Cell, Style, TextDisplayFrame, TextDisplay};
+use crate::graphics::color::{self, Color, RGB};
 use crate::graphics::font::{Font, Glyph};
+use crate::graphics::font::bold::BoldGlyph;
+use crate::graphics::font::hex_box::HexBoxGlyph;
+use crate::graphics::font::scaled::ScaledGlyph;
+use crate::graphics::rect::Rect;
+
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// When the font scale is chosen automatically, it's the largest scale
+/// which still fits at least this many columns and lines on the display.
+/// The idea is that a font is drawn at about the same physical size on any display;
+/// high-resolution displays usually have a higher pixel density too.
+const MIN_AUTO_SCALE_GRID: (usize, usize) = (160, 50);
+
+/// The largest scale a font can be drawn at which leaves enough room for a reasonably large terminal.
+pub fn auto_scale(resolution: (usize, usize), bounding_box: (usize, usize)) -> usize {
+    let (dp_width, dp_height) = resolution;
+    let (ft_width, ft_height) = bounding_box;
+    let (min_columns, min_lines) = MIN_AUTO_SCALE_GRID;
+    let horizontal = dp_width / (ft_width * min_columns).max(1);
+    let vertical = dp_height / (ft_height * min_lines).max(1);
+    horizontal.min(vertical).max(1)
+}
 
 /// A virtual text display that renders itself onto a graphic display.
-pub struct GraphicTextDisplay<'d, 'f, G: Glyph> {
+pub struct GraphicTextDisplay<'d, 'f> {
     display: &'d mut (dyn GraphicDisplay + 'd),
-    font: &'f (dyn Font<Glyph = G> + 'f),
+    font: &'f (dyn Font + 'f),
     frame: TextDisplayFrame,
-    bg: RGB,
-    fg: RGB,
+    /// Every pixel of the font is drawn as a square of this many pixels on each side.
+    scale: usize,
+    cursor_blink: bool,
+    /// Whether a blinking cursor is in the visible part of its blink.
+    cursor_phase: bool,
 }
 
-impl<G: Glyph> GraphicTextDisplay<'_, '_, G> {
+impl GraphicTextDisplay<'_, '_> {
+    /// `bg` and `fg` are the colors of cells which haven't been given any other style.
+    /// The font is scaled up to suit the resolution of the display (see `auto_scale`).
     pub fn new<'d, 'f>
-            (display: &'d mut (dyn GraphicDisplay + 'd), font: &'f (dyn Font<Glyph = G> + 'f),
+            (display: &'d mut (dyn GraphicDisplay + 'd), font: &'f (dyn Font + 'f),
              bg: impl Color, fg: impl Color)
-            -> GraphicTextDisplay<'d, 'f, G> {
+            -> GraphicTextDisplay<'d, 'f> {
+        let scale = auto_scale(display.resolution(), font.bounding_box());
+        GraphicTextDisplay::with_scale(display, font, scale, bg, fg)
+    }
+
+    /// Create a text display which draws each pixel of the font as a `scale` by `scale` square.
+    pub fn with_scale<'d, 'f>
+            (display: &'d mut (dyn GraphicDisplay + 'd), font: &'f (dyn Font + 'f),
+             scale: usize, bg: impl Color, fg: impl Color)
+            -> GraphicTextDisplay<'d, 'f> {
+        let scale = scale.max(1);
         let (dp_width, dp_height) = display.resolution();
         let (ft_width, ft_height) = font.bounding_box();
-        let ch_width = dp_width / ft_width as usize;
-        let ch_height = dp_height / ft_height as usize;
+        let ch_width = dp_width / (ft_width * scale);
+        let ch_height = dp_height / (ft_height * scale);
 
         GraphicTextDisplay {
             display: display,
             font: font,
-            frame: TextDisplayFrame::new((ch_width, ch_height)),
-            bg: bg.into_rgb(),
-            fg: fg.into_rgb(),
+            frame: TextDisplayFrame::new((ch_width, ch_height), Style::new(fg, bg)),
+            scale: scale,
+            cursor_blink: false,
+            cursor_phase: true,
+        }
+    }
+
+    pub fn scale(&self) -> usize {
+        self.scale
+    }
+
+    /// The display the text is drawn on.
+    pub fn display(&self) -> &dyn GraphicDisplay {
+        &*self.display
+    }
+
+    /// The size, in pixels, of each cell on the display.
+    fn cell_size(&self) -> (usize, usize) {
+        let (ft_width, ft_height) = self.font.bounding_box();
+        (ft_width * self.scale, ft_height * self.scale)
+    }
+
+    /// Choose whether the cursor blinks. If it does, `blink` must be called periodically.
+    pub fn set_cursor_blink(&mut self, blink: bool) {
+        self.cursor_blink = blink;
+        self.cursor_phase = true;
+        if let Some((x, y)) = self.frame.cursor() {
+            self.frame.mark_dirty(x, y);
+        }
+    }
+
+    /// Advance a blinking cursor to the next half of its blink.
+    /// The cursor will be redrawn on the next refresh.
+    // TODO: Call this from a timer interrupt once we have one.
+    pub fn blink(&mut self) {
+        if !self.cursor_blink {
+            return;
+        }
+
+        self.cursor_phase = !self.cursor_phase;
+        if let Some((x, y)) = self.frame.cursor() {
+            self.frame.mark_dirty(x, y);
+        }
+    }
+
+    /// The actual foreground and background colors to draw a cell with.
+    fn colors(style: Style) -> (RGB, RGB) {
+        let (mut fg, bg) = if style.attrs.contains(Attributes::INVERSE) {
+            (style.bg, style.fg)
+        } else {
+            (style.fg, style.bg)
+        };
+
+        if style.attrs.contains(Attributes::DIM) {
+            fg = color::blend(bg, fg, 0x80);
+        }
+
+        (fg, bg)
+    }
+
+    /// Draw a cell, which is two columns wide if it holds a wide character.
+    fn draw_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        let (column_width, line_height) = self.cell_size();
+        let columns = if cell.is_wide() && x + 1 < self.frame.width() { 2 } else { 1 };
+        let cell_width = column_width * columns;
+        let px_x = x * column_width;
+        let px_y = y * line_height;
+        let (fg, bg) = Self::colors(cell.style);
+
+        // We don't clear the display, so we have to erase whatever used to be in the cell.
+        self.display.fill_rect(bg, Rect::new(px_x, px_y, cell_width, line_height));
+
+        if cell.ch != '\u{0}' {
+            // A character that's missing from the font shouldn't be able to crash the kernel,
+            // so we show the font's replacement character instead,
+            // or if it doesn't have one, a box with the character's codepoint.
+            let placed;
+            let scaled;
+            let hex_box;
+            let glyph: &dyn Glyph = match self.font.lookup(cell.ch)
+                    .or_else(|| self.font.lookup(REPLACEMENT_CHARACTER)) {
+                Some(glyph) => {
+                    placed = glyph;
+                    scaled = ScaledGlyph::new(&placed, self.scale);
+                    &scaled
+                },
+                // The box is drawn at full size, so that there's as much room for the digits as possible.
+                None => {
+                    hex_box = HexBoxGlyph::new(cell.ch, (column_width, line_height));
+                    &hex_box
+                },
+            };
+            self.draw_glyph(px_x, px_y, (cell_width, line_height), fg, bg, cell.style.attrs, glyph);
+
+            // Combining marks are drawn right on top of the character.
+            // A mark that's missing from the font is left out rather than replaced,
+            // since a box drawn over the character would only make it harder to read.
+            for mark in cell.marks() {
+                if let Some(glyph) = self.font.lookup(mark) {
+                    let glyph = ScaledGlyph::new(&glyph, self.scale);
+                    self.draw_glyph(px_x, px_y, (cell_width, line_height), fg, bg, cell.style.attrs, &glyph);
+                }
+            }
+        }
+
+        if cell.style.attrs.contains(Attributes::UNDERLINE) {
+            // The underline is as thick as a pixel of the font.
+            let thickness = self.scale.min(line_height);
+            self.display.fill_rect(fg, Rect::new(px_x, px_y + line_height - thickness, cell_width, thickness));
+        }
+    }
+
+    fn draw_glyph(&mut self, px_x: usize, px_y: usize, bounding_box: (usize, usize),
+                  fg: RGB, bg: RGB, attrs: Attributes, glyph: &dyn Glyph) {
+        // Bold text is smeared one (font) pixel to the right.
+        let bold;
+        let glyph: &dyn Glyph = if attrs.contains(Attributes::BOLD) {
+            bold = BoldGlyph::new(glyph, self.scale);
+            &bold
+        } else {
+            glyph
+        };
+
+        unsafe {
+            self.display.draw_glyph_blended(bounding_box, px_x, px_y, fg, bg, glyph);
         }
     }
 }
 
-impl<G: Glyph> TextDisplay for GraphicTextDisplay<'_, '_, G> {
+impl TextDisplay for GraphicTextDisplay<'_, '_> {
     fn borrow_frame<'a>(&'a self) -> &'a TextDisplayFrame {
         &self.frame
     }
@@ -41,22 +200,43 @@ impl<G: Glyph> TextDisplay for GraphicTextDisplay<'_, '_, G> {
         &mut self.frame
     }
 
+    fn graphic_display(&self) -> Option<&dyn GraphicDisplay> {
+        Some(self.display())
+    }
+
     fn refresh(&mut self) {
-        self.display.clear(self.bg);
-        for x in 0..self.frame.width() {
-            for y in 0..self.frame.height() {
-                let c = self.frame.get(x, y);
-                if c == '\u{0}' { continue; }
-
-                // FIXME: This code shouldn't throw errors.
-                //   instead, it should display some kind of missing character.
-                let glyph = self.font.lookup(c).expect("Character missing from font.");
-                let px_x = x * self.font.bounding_box().0;
-                let px_y = y * self.font.bounding_box().1;
-                unsafe {
-                    self.display.draw_glyph(self.font.bounding_box(), px_x, px_y, self.fg, glyph);
+        for y in 0..self.frame.height() {
+            for x in 0..self.frame.width() {
+                if !self.frame.is_dirty(x, y) { continue; }
+                let mut cell = self.frame.get(x, y);
+                let mut x = x;
+                if cell.is_continuation() && x > 0 {
+                    // The second half of a wide character is drawn along with the first half,
+                    // which we already did if it was dirty too.
+                    if self.frame.is_dirty(x - 1, y) { continue; }
+                    x -= 1;
+                    cell = self.frame.get(x, y);
                 }
+
+                // The cursor is drawn as a block, by swapping the colors of the cell it's in.
+                // If it's on either half of a wide character, the whole character is highlighted.
+                let cursor = self.frame.cursor();
+                let columns = if cell.is_wide() { 2 } else { 1 };
+                let under_cursor = cursor.map_or(false, |(cx, cy)| cy == y && cx >= x && cx < x + columns);
+                if under_cursor && self.cursor_phase {
+                    cell.style.attrs.toggle(Attributes::INVERSE);
+                }
+
+                // A continuation cell without a wide character before it shouldn't exist,
+                // but if it does, it's displayed as an empty cell.
+                if cell.is_continuation() {
+                    cell.ch = '\u{0}';
+                }
+                self.draw_cell(x, y, cell);
             }
         }
+
+        self.frame.mark_clean();
+        self.display.refresh();
     }
 }
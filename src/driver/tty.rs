@@ -1,6 +1,9 @@
+pub mod ansi;
 pub mod serial;
 pub mod text_display;
 
+use core::fmt;
+
 /// A teletypewriter, or really, because those don't exist anymore,
 /// a device that behaves like or emulates a teletypewriter.
 /// Basically, this is a device that lets you output text and not much else.
@@ -15,3 +18,13 @@ pub trait Tty {
     /// Synchronously flush any buffered output.
     fn flush(&mut self);
 }
+
+/// Lets you use `write!` to print to any TTY, without having to format a `String` first.
+pub struct TtyWriter<'t>(pub &'t mut dyn Tty);
+
+impl fmt::Write for TtyWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.0.puts(s);
+        Ok(())
+    }
+}
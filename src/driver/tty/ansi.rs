@@ -0,0 +1,274 @@
+// A parser for the escape sequences understood by VT100-compatible terminals
+// (or at least, the subset of them that xterm and friends still use).
+//
+// The parser only splits the input up into printable characters, control characters,
+// and escape sequences. It's up to the terminal to decide what they mean.
+// The state machine is a simplified version of the one described at
+// https://vt100.net/emu/dec_ansi_parser.
+
+/// The most parameters we keep for a single control sequence.
+/// Any beyond this are ignored; nothing we support uses more than five.
+const MAX_PARAMS: usize = 16;
+
+/// Something the terminal should do in response to the input.
+pub enum Action<'a> {
+    /// Display a character at the cursor.
+    Print(char),
+    /// Perform the function of a C0 control character, like a line feed or backspace.
+    Execute(char),
+    /// An escape sequence, `ESC [intermediate] final`.
+    Escape { intermediate: Option<char>, final_char: char },
+    /// A control sequence, `CSI [private] params [intermediate] final`.
+    Csi(&'a ControlSequence),
+}
+
+/// A control sequence introduced by `ESC [`.
+pub struct ControlSequence {
+    /// A private marker like the `?` in `CSI ? 25 h`, which changes the sequence's meaning.
+    pub private: Option<char>,
+    params: [u16; MAX_PARAMS],
+    param_count: usize,
+    /// Which parameters are sub-parameters of the one before them,
+    /// i.e. were separated from it by a colon rather than a semicolon.
+    subparams: u32,
+    pub intermediate: Option<char>,
+    pub final_char: char,
+}
+
+impl ControlSequence {
+    const fn new() -> ControlSequence {
+        ControlSequence {
+            private: None,
+            params: [0; MAX_PARAMS],
+            param_count: 0,
+            subparams: 0,
+            intermediate: None,
+            final_char: '\u{0}',
+        }
+    }
+
+    /// All of the parameters which were given.
+    /// Parameters which were left empty are zero.
+    pub fn params(&self) -> &[u16] {
+        &self.params[..self.param_count]
+    }
+
+    /// The parameters, each along with its sub-parameters (e.g. `38:2:r:g:b` is one group).
+    /// Parameters which are only separated by semicolons are each a group of their own.
+    pub fn groups(&self) -> impl Iterator<Item = &[u16]> {
+        let params = self.params();
+        let mut start = 0;
+        core::iter::from_fn(move || {
+            if start >= params.len() {
+                return None;
+            }
+            let mut end = start + 1;
+            while end < params.len() && self.subparams & 1 << end != 0 {
+                end += 1;
+            }
+            let group = &params[start..end];
+            start = end;
+            Some(group)
+        })
+    }
+
+    /// Get a parameter, or `default` if it was left out or is zero.
+    /// Most sequences treat zero the same as leaving the parameter out.
+    pub fn param(&self, i: usize, default: u16) -> u16 {
+        match self.params().get(i) {
+            Some(&p) if p != 0 => p,
+            _ => default,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    /// We've seen something in a control sequence which we don't understand,
+    /// so we ignore everything until the end of the sequence.
+    CsiIgnore,
+    /// An operating system command (`ESC ]`) or other string sequence,
+    /// which we ignore until the string terminator.
+    String,
+    /// We've seen an escape in a string, which may be the start of the string terminator.
+    StringEscape,
+}
+
+pub struct Parser {
+    state: State,
+    intermediate: Option<char>,
+    csi: ControlSequence,
+}
+
+const ESC: char = '\u{1B}';
+const BEL: char = '\u{07}';
+const CAN: char = '\u{18}';
+const SUB: char = '\u{1A}';
+const DEL: char = '\u{7F}';
+
+fn is_c0(c: char) -> bool {
+    c < ' '
+}
+
+/// C1 control characters are the 8-bit equivalents of escape sequences.
+/// Nothing sends them, and they're easily confused with UTF-8, so we ignore them.
+fn is_c1(c: char) -> bool {
+    ('\u{80}'..='\u{9F}').contains(&c)
+}
+
+impl Parser {
+    pub const fn new() -> Parser {
+        Parser {
+            state: State::Ground,
+            intermediate: None,
+            csi: ControlSequence::new(),
+        }
+    }
+
+    /// Abandon whatever sequence is in progress.
+    pub fn reset(&mut self) {
+        self.state = State::Ground;
+    }
+
+    fn begin_csi(&mut self) {
+        self.csi = ControlSequence::new();
+        self.state = State::CsiEntry;
+    }
+
+    fn push_digit(&mut self, digit: u16) {
+        if self.csi.param_count == 0 {
+            self.csi.param_count = 1;
+        }
+        if self.csi.param_count <= MAX_PARAMS {
+            let p = &mut self.csi.params[self.csi.param_count - 1];
+            *p = p.saturating_mul(10).saturating_add(digit);
+        }
+    }
+
+    fn next_param(&mut self, subparam: bool) {
+        // An empty first parameter still counts as a parameter.
+        if self.csi.param_count == 0 {
+            self.csi.param_count = 1;
+        }
+        if self.csi.param_count < MAX_PARAMS {
+            if subparam {
+                self.csi.subparams |= 1 << self.csi.param_count;
+            }
+            self.csi.param_count += 1;
+        } else {
+            // Any further digits will be ignored.
+            self.csi.param_count = MAX_PARAMS + 1;
+        }
+    }
+
+    /// Feed the next character of input to the parser,
+    /// which calls `perform` with any resulting action.
+    pub fn advance(&mut self, c: char, mut perform: impl FnMut(Action)) {
+        // These work the same no matter where we are in a sequence.
+        match c {
+            CAN | SUB => {
+                self.state = State::Ground;
+                return;
+            },
+            ESC if self.state == State::String => {
+                self.state = State::StringEscape;
+                return;
+            },
+            ESC => {
+                self.intermediate = None;
+                self.state = State::Escape;
+                return;
+            },
+            _ if is_c1(c) => return,
+            _ => {},
+        }
+
+        if self.state == State::StringEscape {
+            // `ESC \` is the string terminator.
+            if c == '\\' {
+                self.state = State::Ground;
+                return;
+            }
+
+            // Anything else means the escape was the start of a new escape sequence.
+            self.intermediate = None;
+            self.state = State::Escape;
+        }
+
+        match self.state {
+            State::Ground => {
+                if is_c0(c) {
+                    perform(Action::Execute(c));
+                } else if c != DEL {
+                    perform(Action::Print(c));
+                }
+            },
+            State::Escape | State::EscapeIntermediate => match c {
+                _ if is_c0(c) => perform(Action::Execute(c)),
+                DEL => {},
+                ' '..='/' => {
+                    self.intermediate = Some(c);
+                    self.state = State::EscapeIntermediate;
+                },
+                '[' if self.state == State::Escape => self.begin_csi(),
+                // Operating system command, device control string,
+                // start of string, privacy message, and application program command.
+                // We don't support any of them, but we need to skip over them properly.
+                ']' | 'P' | 'X' | '^' | '_' if self.state == State::Escape => {
+                    self.state = State::String;
+                },
+                '0'..='~' => {
+                    self.state = State::Ground;
+                    perform(Action::Escape { intermediate: self.intermediate, final_char: c });
+                },
+                _ => self.state = State::Ground,
+            },
+            State::CsiEntry | State::CsiParam | State::CsiIntermediate | State::CsiIgnore => match c {
+                _ if is_c0(c) => perform(Action::Execute(c)),
+                DEL => {},
+                _ if self.state == State::CsiIgnore => {
+                    if ('@'..='~').contains(&c) {
+                        self.state = State::Ground;
+                    }
+                },
+                '<'..='?' if self.state == State::CsiEntry => {
+                    self.csi.private = Some(c);
+                    self.state = State::CsiParam;
+                },
+                '0'..='9' if self.state != State::CsiIntermediate => {
+                    self.push_digit(c as u16 - '0' as u16);
+                    self.state = State::CsiParam;
+                },
+                // Colons separate sub-parameters, e.g. `38:2:r:g:b`.
+                ';' | ':' if self.state != State::CsiIntermediate => {
+                    self.next_param(c == ':');
+                    self.state = State::CsiParam;
+                },
+                ' '..='/' => {
+                    self.csi.intermediate = Some(c);
+                    self.state = State::CsiIntermediate;
+                },
+                '@'..='~' => {
+                    self.state = State::Ground;
+                    self.csi.param_count = self.csi.param_count.min(MAX_PARAMS);
+                    self.csi.final_char = c;
+                    perform(Action::Csi(&self.csi));
+                },
+                _ => self.state = State::CsiIgnore,
+            },
+            // We've already dealt with the string terminator above.
+            State::String | State::StringEscape => {
+                // Some programs end OSC with BEL instead of the string terminator.
+                if c == BEL {
+                    self.state = State::Ground;
+                }
+            },
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use core::fmt;
 use crate::driver::tty::Tty;
 
 /// A TTY attached via a serial port.
@@ -42,6 +43,12 @@ impl SerialTty {
 
 impl Tty for SerialTty {
     fn putc(&mut self, c: char) {
+        // Terminals expect a carriage return before each line feed,
+        // and `TextDisplayTty` treats a line feed as both, so we do the same here
+        // to make output look the same on both.
+        if c == '\n' {
+            self.outc('\r');
+        }
         self.outc(c);
     }
 
@@ -60,3 +67,10 @@ impl Tty for SerialTty {
         // This TTY doesn't use buffering.
     }
 }
+
+impl fmt::Write for SerialTty {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.puts(s);
+        Ok(())
+    }
+}
@@ -1,35 +1,103 @@
-use alloc::string::{String, ToString};
-use alloc::vec::Vec;
-use crate::driver::text_display::TextDisplay;
+mod screen;
+mod scrollback;
+
+use crate::driver::text_display::{Cell, Style, TextDisplay};
 use crate::driver::tty::Tty;
+use crate::driver::tty::ansi::Parser;
+use self::screen::Screen;
+
+/// The number of lines kept in the scrollback, unless otherwise specified.
+const DEFAULT_SCROLLBACK: usize = 1000;
 
-/// A buffered virtual TTY implemented over a textual display.
+/// A buffered virtual terminal implemented over a textual display.
+///
+/// Output is interpreted the same way a VT100-compatible terminal (like xterm) would,
+/// so escape sequences for moving the cursor, erasing, changing colors, and so on
+/// display the same way here as they do over a serial port.
 pub struct TextDisplayTty<'display> {
     term: &'display mut (dyn TextDisplay + 'display),
-    history: Vec<String>,
+    parser: Parser,
+    screen: Screen,
+    /// How many lines back into the scrollback the display is scrolled.
+    /// When this is zero, the display shows the screen as usual.
+    view_offset: usize,
+    /// The value of `screen.scrolled_off()` as of the last flush.
+    scrolled_off: usize,
+    /// Whether every line of the display has to be redrawn,
+    /// rather than only the ones which have changed.
+    redraw: bool,
 }
 
 impl TextDisplayTty<'_> {
     pub fn new<'a>(term: &'a mut dyn TextDisplay) -> TextDisplayTty<'a> {
+        TextDisplayTty::with_scrollback(term, DEFAULT_SCROLLBACK)
+    }
+
+    /// Create a TTY which keeps up to `scrollback` lines after they've scrolled off of the display.
+    pub fn with_scrollback<'a>(term: &'a mut dyn TextDisplay, scrollback: usize) -> TextDisplayTty<'a> {
+        let frame = term.borrow_frame();
+        let screen = Screen::new(frame.resolution(), frame.default_style(), scrollback);
         TextDisplayTty {
             term,
-            history: {
-                let mut vec = Vec::new();
-                vec.push("".to_string());
-                vec
-            },
+            parser: Parser::new(),
+            screen: screen,
+            view_offset: 0,
+            scrolled_off: 0,
+            redraw: true,
         }
     }
+
+    /// The display the TTY is shown on.
+    pub fn display(&self) -> &dyn TextDisplay {
+        &*self.term
+    }
+
+    pub fn style(&self) -> Style {
+        self.screen.style()
+    }
+
+    /// Set the style of all characters written from now on.
+    /// Characters which have already been written keep the style they were written with.
+    pub fn set_style(&mut self, style: Style) {
+        self.screen.set_style(style);
+    }
+
+    /// Go back to writing characters in the display's default style.
+    pub fn reset_style(&mut self) {
+        self.screen.reset_style();
+    }
+
+    /// How many lines back into the scrollback the display is scrolled.
+    pub fn view_offset(&self) -> usize {
+        self.view_offset
+    }
+
+    // TODO: Bind these to Shift+PgUp and Shift+PgDn once we have a keyboard driver.
+
+    /// Scroll the display back through the scrollback by up to `n` lines.
+    /// The display will stay scrolled back until it's scrolled down again,
+    /// even as new output is written.
+    pub fn scroll_view_up(&mut self, n: usize) {
+        self.view_offset = (self.view_offset + n).min(self.screen.scrollback().len());
+        self.redraw = true;
+    }
+
+    /// Scroll the display forward by up to `n` lines, towards the current output.
+    pub fn scroll_view_down(&mut self, n: usize) {
+        self.view_offset = self.view_offset.saturating_sub(n);
+        self.redraw = true;
+    }
+
+    /// Scroll the display all the way forward to the current output.
+    pub fn scroll_view_to_bottom(&mut self) {
+        self.scroll_view_down(self.view_offset);
+    }
 }
 
 impl Tty for TextDisplayTty<'_> {
     fn putc(&mut self, c: char) {
-        if c == '\n' {
-            self.history.push("".to_string());
-            return;
-        }
-        let i = self.history.len() - 1;
-        self.history[i].push(c);
+        let screen = &mut self.screen;
+        self.parser.advance(c, |action| screen.perform(action));
     }
 
     fn puts(&mut self, s: &str) {
@@ -39,55 +107,60 @@ impl Tty for TextDisplayTty<'_> {
     }
 
     fn clear(&mut self) {
-        self.history.clear();
-        self.history.push("".to_string());
+        self.parser.reset();
+        self.screen.reset();
     }
 
     fn flush(&mut self) {
-        // Each line of the history represents a virtual line of output.
-        // However, a line of output may be longer than the physical width of the display,
-        // in which case we may need to wrap the line so that it takes up two physical lines.
-        let mut physical_lines = Vec::new();
-        for line in &self.history {
-            let mut chars = line.chars().collect::<Vec<_>>().into_iter();
-            // We iterate over all of the characters in a virtual line
-            // until every character has been added to a physical line.
-            // It is necessary that we iterate at least once, or empty lines will not be printed.
-            loop {
-                let mut physical_line = String::new();
-                // The width of a physical line may be no more than the width of the frame.
-                let width = chars.len().min(self.term.borrow_frame().width());
-                for _ in 0..width {
-                    physical_line.push(chars.next().unwrap());
-                }
-                physical_lines.push(physical_line);
-
-                if chars.len() == 0 {
-                    break;
-                }
-            }
+        let resolution = self.term.borrow_frame().resolution();
+        if resolution != self.screen.resolution() {
+            self.screen.resize(resolution);
+            self.view_offset = 0;
+            self.redraw = true;
+        }
+
+        // If we're scrolled back, we stay on the same lines as more lines are added,
+        // so that they don't scroll away while they're being read.
+        let scrolled_off = self.screen.scrolled_off();
+        if self.view_offset > 0 {
+            self.view_offset += scrolled_off.wrapping_sub(self.scrolled_off);
+            self.view_offset = self.view_offset.min(self.screen.scrollback().len());
         }
+        self.scrolled_off = scrolled_off;
 
-        // This is how many lines on the display we'll need for all of our physical lines.
-        // We cannot have more lines than allowed by the display.
-        let mut y = physical_lines.len().min(self.term.borrow_frame().height()) - 1;
+        let (width, height) = resolution;
+        let blank = Cell::blank(self.term.borrow_frame().default_style());
+        let scrollback = self.screen.scrollback();
         let frame = self.term.borrow_mut_frame();
-        // We start from the lowest line and display each line until we reach the top of the screen.
-        // We cannot run out of physical lines because the lowest line
-        // is at lowest the number of physical lines necessary to display all lines.
-        for line in physical_lines.into_iter().rev() {
-            let mut x = 0;
-            for c in line.chars() {
-                frame.set(x, y, c);
-                x += 1;
+        for y in 0..height {
+            // Ordinarily, only lines which were written to or scrolled since the last flush are dirty,
+            // so there's no point in copying the rest to the display.
+            // If we're scrolled back, everything moves around, so we let the frame sort out
+            // which cells actually changed.
+            if self.view_offset == 0 && !self.redraw && !self.screen.is_dirty(y) {
+                continue;
             }
 
-            if y == 0 {
-                break;
-            }
+            // Lines of the scrollback are displayed above the lines of the screen.
+            let i = scrollback.len() - self.view_offset + y;
+            let line = if i < scrollback.len() {
+                scrollback.get(i)
+            } else {
+                self.screen.line(i - scrollback.len())
+            };
 
-            y -= 1;
+            for x in 0..width {
+                frame.set(x, y, line.cells.get(x).copied().unwrap_or(blank));
+            }
         }
+        self.screen.mark_clean();
+        self.redraw = false;
+
+        // The cursor may have been scrolled off of the bottom of the display.
+        let cursor = self.screen.cursor()
+            .map(|(x, y)| (x, y + self.view_offset))
+            .filter(|&(_, y)| y < height);
+        frame.set_cursor(cursor);
 
         self.term.refresh();
     }
@@ -0,0 +1,800 @@
+use alloc::vec::Vec;
+use crate::driver::text_display::{Attributes, Cell, Style};
+use crate::driver::tty::ansi::{Action, ControlSequence};
+use crate::graphics::color::RGB;
+use crate::graphics::color::palette::Palette;
+use crate::unicode;
+use super::scrollback::{Line, Scrollback};
+
+const REPLACEMENT_CHARACTER: char = '\u{FFFD}';
+
+/// The state of a virtual terminal: what's on the screen, where the cursor is,
+/// and how newly-written characters should look.
+///
+/// This interprets input the same way a VT100-compatible terminal (like xterm) would,
+/// but it doesn't know how to display itself; that's up to `TextDisplayTty`.
+pub struct Screen {
+    resolution: (usize, usize),
+    lines: Vec<Line>,
+    scrollback: Scrollback,
+    /// The total number of lines which have ever been added to the scrollback.
+    scrolled_off: usize,
+    /// Which lines have changed since they were last displayed.
+    dirty: Vec<bool>,
+    /// The position (column, line) where the next character will be written.
+    /// The column may be one past the end of the line, in which case the next character wraps.
+    cursor: (usize, usize),
+    saved_cursor: ((usize, usize), Style),
+    /// The top and bottom lines (inclusive) of the region which scrolls.
+    scroll_region: (usize, usize),
+    /// The columns which tab characters advance the cursor to.
+    tab_stops: Vec<bool>,
+    cursor_visible: bool,
+    /// Whether characters written past the end of a line wrap onto the next line
+    /// (rather than overwriting the last character on the line).
+    autowrap: bool,
+    /// Whether written characters push the rest of the line to the right
+    /// (rather than overwriting the character under the cursor).
+    insert: bool,
+    style: Style,
+    default_style: Style,
+    /// The colors picked by number in escape sequences.
+    palette: Palette,
+}
+
+/// Tab stops are initially set every eight columns.
+fn default_tab_stops(width: usize) -> Vec<bool> {
+    (0..width).map(|x| x % 8 == 0).collect()
+}
+
+impl Screen {
+    /// `scrollback` is the number of lines which are kept after they scroll off of the screen.
+    pub fn new(resolution: (usize, usize), default_style: Style, scrollback: usize) -> Screen {
+        let (width, height) = resolution;
+        let mut lines = Vec::new();
+        lines.resize(height, Line::blank(width, Cell::blank(default_style)));
+        let mut dirty = Vec::new();
+        dirty.resize(height, true);
+
+        Screen {
+            resolution: resolution,
+            lines: lines,
+            scrollback: Scrollback::new(scrollback, Cell::blank(default_style)),
+            scrolled_off: 0,
+            dirty: dirty,
+            cursor: (0, 0),
+            saved_cursor: ((0, 0), default_style),
+            scroll_region: (0, height.saturating_sub(1)),
+            tab_stops: default_tab_stops(width),
+            cursor_visible: true,
+            autowrap: true,
+            insert: false,
+            style: default_style,
+            default_style: default_style,
+            palette: Palette::XTERM,
+        }
+    }
+
+    pub fn resolution(&self) -> (usize, usize) {
+        self.resolution
+    }
+
+    pub fn width(&self) -> usize {
+        self.resolution.0
+    }
+
+    pub fn height(&self) -> usize {
+        self.resolution.1
+    }
+
+    pub fn line(&self, y: usize) -> &Line {
+        &self.lines[y]
+    }
+
+    pub fn scrollback(&self) -> &Scrollback {
+        &self.scrollback
+    }
+
+    /// The total number of lines which have ever been added to the scrollback
+    /// (which may be more than are in it, since old lines are thrown away).
+    /// It wraps around on overflow, so it's only useful for comparing against an earlier count.
+    pub fn scrolled_off(&self) -> usize {
+        self.scrolled_off
+    }
+
+    pub fn is_dirty(&self, y: usize) -> bool {
+        self.dirty[y]
+    }
+
+    pub fn mark_clean(&mut self) {
+        for dirty in self.dirty.iter_mut() {
+            *dirty = false;
+        }
+    }
+
+    /// The cell the cursor is in, if the cursor is visible.
+    pub fn cursor(&self) -> Option<(usize, usize)> {
+        if !self.cursor_visible || self.width() == 0 || self.height() == 0 {
+            return None;
+        }
+
+        // If we're waiting to wrap, the cursor is still shown on the last column.
+        let (x, y) = self.cursor;
+        Some((x.min(self.width() - 1), y))
+    }
+
+    pub fn style(&self) -> Style {
+        self.style
+    }
+
+    pub fn set_style(&mut self, style: Style) {
+        self.style = style;
+    }
+
+    pub fn reset_style(&mut self) {
+        self.style = self.default_style;
+    }
+
+    /// Change the colors picked by number in escape sequences.
+    /// Text which has already been written keeps its colors.
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+    }
+
+    /// Erased cells keep the current colors, but not any other attributes (like xterm).
+    fn blank(&self) -> Cell {
+        Cell::blank(Style { fg: self.style.fg, bg: self.style.bg, attrs: Attributes::NONE })
+    }
+
+    /// Put everything back the way it was when the screen was created.
+    pub fn reset(&mut self) {
+        self.style = self.default_style;
+        self.cursor = (0, 0);
+        self.saved_cursor = ((0, 0), self.default_style);
+        self.scroll_region = (0, self.height().saturating_sub(1));
+        self.tab_stops = default_tab_stops(self.width());
+        self.cursor_visible = true;
+        self.autowrap = true;
+        self.insert = false;
+        self.erase_lines(0, self.height());
+    }
+
+    /// Change the size of the screen, re-wrapping the screen and the scrollback to fit.
+    pub fn resize(&mut self, resolution: (usize, usize)) {
+        let (width, height) = resolution;
+        let blank = Cell::blank(self.default_style);
+
+        // Everything below the cursor and the last line of text is empty, so we don't keep it.
+        let used = self.lines.iter().rposition(|l| !l.content().is_empty())
+            .map_or(0, |y| y + 1)
+            .max(self.cursor.1 + 1)
+            .min(self.lines.len());
+        let (cursor_x, cursor_y) = self.cursor;
+
+        // First, we join the wrapped physical lines back together into the lines of text
+        // they originally were, keeping track of where in the text the cursor was.
+        let mut texts: Vec<Vec<Cell>> = Vec::new();
+        let mut cursor = None;
+        let mut text = Vec::new();
+        let old_lines = self.scrollback.drain().chain(self.lines.drain(..used)).collect::<Vec<_>>();
+        let scrollback_len = old_lines.len() - used;
+        for (i, line) in old_lines.iter().enumerate() {
+            if i == scrollback_len + cursor_y {
+                cursor = Some((texts.len(), text.len() + cursor_x));
+            }
+
+            let mut content = line.content();
+            // A wide character which didn't fit at the end of a line was wrapped early,
+            // leaving behind an empty cell which isn't really part of the text.
+            let next_is_wide = old_lines.get(i + 1)
+                .and_then(|next| next.cells.first())
+                .map_or(false, |cell| cell.is_wide());
+            if line.wrapped && next_is_wide && content.last().map_or(false, |cell| cell.ch == '\u{0}') {
+                content = &content[..content.len() - 1];
+            }
+
+            text.extend_from_slice(content);
+            if !line.wrapped {
+                texts.push(core::mem::replace(&mut text, Vec::new()));
+            }
+        }
+        if !text.is_empty() {
+            texts.push(text);
+        }
+
+        // Then we wrap the text again to fit the new width,
+        // and find out where the cursor ended up.
+        // (Lines are always at least one cell wide while we do this, so that text has somewhere to go;
+        // on a screen with no columns at all, they're cut down to size afterwards.)
+        let wrap_width = width.max(1);
+        let mut lines = Vec::new();
+        let (mut new_cursor_x, mut new_cursor_y) = (0, 0);
+        for (i, text) in texts.iter().enumerate() {
+            let cursor_offset = cursor.filter(|&(t, _)| t == i).map(|(_, offset)| offset);
+            // Every line of text takes up at least one physical line, even if it's empty.
+            let mut line = Line::blank(wrap_width, blank);
+            let mut x: usize = 0;
+            for (offset, &cell) in text.iter().enumerate() {
+                // Continuation cells are copied along with the wide character they belong to.
+                if cell.is_continuation() {
+                    if cursor_offset == Some(offset) {
+                        new_cursor_x = x.saturating_sub(1);
+                        new_cursor_y = lines.len();
+                    }
+                    continue;
+                }
+
+                // A wide character can't fit on a line that's only one column wide.
+                let cell = if cell.is_wide() && wrap_width < 2 {
+                    Cell::new(REPLACEMENT_CHARACTER, cell.style)
+                } else {
+                    cell
+                };
+                let cell_width = if cell.is_wide() { 2 } else { 1 };
+
+                // Wide characters wrap early instead of being split between lines.
+                if x + cell_width > wrap_width {
+                    line.wrapped = true;
+                    lines.push(core::mem::replace(&mut line, Line::blank(wrap_width, blank)));
+                    x = 0;
+                }
+
+                if cursor_offset == Some(offset) {
+                    new_cursor_x = x;
+                    new_cursor_y = lines.len();
+                }
+
+                line.cells[x] = cell;
+                if cell_width == 2 {
+                    line.cells[x + 1] = Cell::continuation(cell.style);
+                }
+                x += cell_width;
+            }
+
+            match cursor_offset {
+                // The cursor was after the end of the text, possibly waiting to wrap.
+                Some(offset) if offset >= text.len() => {
+                    let mut col = x + (offset - text.len());
+                    let mut row = lines.len();
+                    while col > wrap_width {
+                        col -= wrap_width;
+                        row += 1;
+                    }
+                    lines.push(line);
+                    while row >= lines.len() {
+                        lines.push(Line::blank(wrap_width, blank));
+                    }
+                    new_cursor_x = col;
+                    new_cursor_y = row;
+                },
+                _ => lines.push(line),
+            }
+        }
+        if width == 0 {
+            for line in lines.iter_mut() {
+                line.cells.clear();
+            }
+        }
+
+        // Whatever doesn't fit on the screen goes in the scrollback.
+        let top = lines.len().saturating_sub(height).min(new_cursor_y);
+        for line in lines.drain(..top) {
+            self.scrollback.push(&line);
+        }
+        lines.resize(height, Line::blank(width, blank));
+        lines.truncate(height);
+
+        self.resolution = resolution;
+        self.lines = lines;
+        self.dirty.clear();
+        self.dirty.resize(height, true);
+        self.cursor = (new_cursor_x, new_cursor_y - top);
+        self.saved_cursor = ((0, 0), self.saved_cursor.1);
+        self.scroll_region = (0, height.saturating_sub(1));
+        self.tab_stops = default_tab_stops(width);
+    }
+
+    /// Move the cursor, keeping it on the screen.
+    fn move_cursor(&mut self, x: usize, y: usize) {
+        self.cursor = (x.min(self.width().saturating_sub(1)), y.min(self.height().saturating_sub(1)));
+    }
+
+    /// Erase whatever is left of a wide character that was cut in half
+    /// by changing the cells on one side of the boundary between columns `x - 1` and `x`.
+    fn repair_wide(&mut self, y: usize, x: usize) {
+        let width = self.width();
+        let blank = self.blank();
+        let cells = &mut self.lines[y].cells;
+        let wide_before = x > 0 && x <= width && cells[x - 1].is_wide();
+        let continuation_after = x < width && cells[x].is_continuation();
+        if wide_before && !continuation_after {
+            cells[x - 1] = blank;
+        } else if continuation_after && !wide_before {
+            cells[x] = blank;
+        }
+    }
+
+    fn erase_cells(&mut self, y: usize, from: usize, to: usize) {
+        let blank = self.blank();
+        let to = to.min(self.width());
+        let from = from.min(to);
+        for x in from..to {
+            self.lines[y].cells[x] = blank;
+        }
+        self.repair_wide(y, from);
+        self.repair_wide(y, to);
+        // If we erased the end of the line, there's nothing left to continue onto the next line.
+        if to == self.width() {
+            self.lines[y].wrapped = false;
+        }
+        self.dirty[y] = true;
+    }
+
+    /// Insert `n` blank cells at the cursor, pushing the rest of the line to the right.
+    /// Anything pushed past the end of the line is lost.
+    fn insert_cells(&mut self, n: usize) {
+        let (x, y) = self.cursor;
+        let x = x.min(self.width() - 1);
+        let n = n.min(self.width() - x);
+        let blank = self.blank();
+        let line = &mut self.lines[y].cells;
+        line[x..].rotate_right(n);
+        for cell in &mut line[x..x + n] {
+            *cell = blank;
+        }
+        self.repair_wide(y, x);
+        self.repair_wide(y, x + n);
+        self.repair_wide(y, self.width());
+        self.dirty[y] = true;
+    }
+
+    /// Delete `n` cells at the cursor, pulling the rest of the line to the left.
+    fn delete_cells(&mut self, n: usize) {
+        let (x, y) = self.cursor;
+        let x = x.min(self.width() - 1);
+        let n = n.min(self.width() - x);
+        let blank = self.blank();
+        let line = &mut self.lines[y].cells;
+        line[x..].rotate_left(n);
+        let width = line.len();
+        for cell in &mut line[width - n..] {
+            *cell = blank;
+        }
+        self.repair_wide(y, x);
+        self.repair_wide(y, width - n);
+        self.dirty[y] = true;
+    }
+
+    fn erase_lines(&mut self, from: usize, to: usize) {
+        for y in from..to.min(self.height()) {
+            self.erase_cells(y, 0, self.width());
+        }
+    }
+
+    /// Scroll the lines in `top..=bottom` up by `n`, filling in the bottom with blank lines.
+    /// Lines scrolled off the top of the screen are saved in the scrollback.
+    fn scroll_up_region(&mut self, top: usize, bottom: usize, n: usize) {
+        let n = n.min(bottom + 1 - top);
+        for _ in 0..n {
+            let mut line = self.lines.remove(top);
+            if top == 0 {
+                self.scrollback.push(&line);
+                self.scrolled_off = self.scrolled_off.wrapping_add(1);
+            }
+
+            for cell in line.cells.iter_mut() {
+                *cell = self.blank();
+            }
+            line.wrapped = false;
+            self.lines.insert(bottom, line);
+        }
+
+        for y in top..=bottom {
+            self.dirty[y] = true;
+        }
+    }
+
+    /// Scroll the lines in `top..=bottom` down by `n`, filling in the top with blank lines.
+    fn scroll_down_region(&mut self, top: usize, bottom: usize, n: usize) {
+        let n = n.min(bottom + 1 - top);
+        for _ in 0..n {
+            let mut line = self.lines.remove(bottom);
+            for cell in line.cells.iter_mut() {
+                *cell = self.blank();
+            }
+            line.wrapped = false;
+            self.lines.insert(top, line);
+        }
+
+        for y in top..=bottom {
+            self.dirty[y] = true;
+        }
+    }
+
+    fn scroll_up(&mut self, n: usize) {
+        let (top, bottom) = self.scroll_region;
+        self.scroll_up_region(top, bottom, n);
+    }
+
+    fn scroll_down(&mut self, n: usize) {
+        let (top, bottom) = self.scroll_region;
+        self.scroll_down_region(top, bottom, n);
+    }
+
+    /// Move the cursor down a line, scrolling if it's at the bottom of the scroll region.
+    fn line_feed(&mut self) {
+        let (x, y) = self.cursor;
+        if y == self.scroll_region.1 {
+            self.scroll_up(1);
+        } else if y + 1 < self.height() {
+            self.cursor = (x, y + 1);
+        }
+    }
+
+    /// Move the cursor up a line, scrolling if it's at the top of the scroll region.
+    fn reverse_line_feed(&mut self) {
+        let (x, y) = self.cursor;
+        if y == self.scroll_region.0 {
+            self.scroll_down(1);
+        } else if y > 0 {
+            self.cursor = (x, y - 1);
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        // If anyone actually prints this, it mustn't be mistaken for half of a wide character.
+        let c = if c == Cell::CONTINUATION { REPLACEMENT_CHARACTER } else { c };
+        let width = unicode::width(c);
+        if width == 0 {
+            self.combine(c);
+            return;
+        }
+        // A wide character can't fit on a screen that's only one column wide.
+        let (c, width) = if width > self.width() { (REPLACEMENT_CHARACTER, 1) } else { (c, width) };
+
+        // Writing to the last column doesn't wrap immediately;
+        // we wait until there's actually another character to write.
+        // That way, a program can fill a line without scrolling the screen.
+        // A wide character which doesn't fit on the rest of the line wraps early,
+        // leaving the last column empty.
+        if self.cursor.0 + width > self.width() {
+            if self.autowrap {
+                let (x, y) = self.cursor;
+                self.erase_cells(y, x, self.width());
+                self.lines[y].wrapped = true;
+                self.cursor.0 = 0;
+                self.line_feed();
+            } else {
+                self.cursor.0 = self.width() - width;
+            }
+        }
+
+        if self.insert {
+            self.insert_cells(width);
+        }
+
+        let (x, y) = self.cursor;
+        self.lines[y].cells[x] = Cell::new(c, self.style);
+        if width == 2 {
+            self.lines[y].cells[x + 1] = Cell::continuation(self.style);
+        }
+        self.repair_wide(y, x);
+        self.repair_wide(y, x + width);
+        self.dirty[y] = true;
+        self.cursor.0 += width;
+    }
+
+    /// Add a combining mark to the character before the cursor.
+    fn combine(&mut self, mark: char) {
+        let (x, y) = self.cursor;
+        // The previous character may be at the end of the previous line, if it wrapped.
+        let (mut x, y) = if x > 0 {
+            (x.min(self.width()) - 1, y)
+        } else if y > 0 && self.lines[y - 1].wrapped {
+            (self.width() - 1, y - 1)
+        } else {
+            // There's nothing to combine it with.
+            return;
+        };
+
+        if x > 0 && self.lines[y].cells[x].is_continuation() {
+            x -= 1;
+        }
+        // A character with too many marks on it is unreadable anyway, so extra marks are dropped.
+        self.lines[y].cells[x].push_mark(mark);
+        self.dirty[y] = true;
+    }
+
+    /// Move the cursor to the next tab stop, or the end of the line if there aren't any more.
+    fn tab_forward(&mut self, n: usize) {
+        let (mut x, y) = self.cursor;
+        for _ in 0..n {
+            x += 1;
+            while x < self.width() && !self.tab_stops[x] {
+                x += 1;
+            }
+        }
+        self.move_cursor(x, y);
+    }
+
+    /// Move the cursor to the previous tab stop, or the start of the line.
+    fn tab_backward(&mut self, n: usize) {
+        let (x, y) = self.cursor;
+        let mut x = x.min(self.width() - 1);
+        for _ in 0..n {
+            x = x.saturating_sub(1);
+            while x > 0 && !self.tab_stops[x] {
+                x -= 1;
+            }
+        }
+        self.move_cursor(x, y);
+    }
+
+    fn execute(&mut self, c: char) {
+        match c {
+            // Carriage return.
+            '\r' => self.cursor.0 = 0,
+            // Backspace. This only moves the cursor; it doesn't erase anything.
+            // (If the cursor is waiting to wrap, it's really on the last column.)
+            '\u{08}' => self.cursor.0 = self.cursor.0.min(self.width() - 1).saturating_sub(1),
+            // Horizontal tab.
+            '\t' => self.tab_forward(1),
+            // Line feed, vertical tab, and form feed.
+            // Like a terminal with `onlcr` set, a line feed also returns to the start of the line,
+            // because that's what everyone who writes `\n` actually means.
+            '\n' | '\u{0B}' | '\u{0C}' => {
+                self.cursor.0 = 0;
+                self.line_feed();
+            },
+            _ => {},
+        }
+    }
+
+    fn escape(&mut self, intermediate: Option<char>, final_char: char) {
+        if intermediate.is_some() {
+            // Character set selection and so on, none of which we support.
+            return;
+        }
+
+        match final_char {
+            // Reset to initial state.
+            'c' => self.reset(),
+            // Save and restore the cursor.
+            '7' => self.saved_cursor = (self.cursor, self.style),
+            '8' => {
+                let ((x, y), style) = self.saved_cursor;
+                self.move_cursor(x, y);
+                self.style = style;
+            },
+            // Index.
+            'D' => self.line_feed(),
+            // Next line.
+            'E' => {
+                self.cursor.0 = 0;
+                self.line_feed();
+            },
+            // Reverse index.
+            'M' => self.reverse_line_feed(),
+            // Set a tab stop at the cursor.
+            'H' => {
+                let x = self.cursor.0.min(self.width() - 1);
+                self.tab_stops[x] = true;
+            },
+            _ => {},
+        }
+    }
+
+    /// Set or reset a mode (`CSI h` and `CSI l`).
+    fn set_mode(&mut self, private: bool, mode: u16, value: bool) {
+        match (private, mode) {
+            // Insert mode.
+            (false, 4) => self.insert = value,
+            // Autowrap mode.
+            (true, 7) => self.autowrap = value,
+            // Cursor visibility.
+            (true, 25) => self.cursor_visible = value,
+            _ => {},
+        }
+    }
+
+    fn csi(&mut self, seq: &ControlSequence) {
+        if seq.final_char == 'h' || seq.final_char == 'l' {
+            let private = seq.private == Some('?');
+            for &mode in seq.params() {
+                self.set_mode(private, mode, seq.final_char == 'h');
+            }
+            return;
+        }
+
+        if seq.private.is_some() || seq.intermediate.is_some() {
+            // We don't support any other private sequences.
+            return;
+        }
+
+        let (x, y) = self.cursor;
+        let n = seq.param(0, 1) as usize;
+        match seq.final_char {
+            // Cursor up, down, forward, and back.
+            'A' => self.move_cursor(x, y.saturating_sub(n).max(self.region_top_for(y))),
+            'B' => self.move_cursor(x, (y + n).min(self.region_bottom_for(y))),
+            'C' => self.move_cursor(x + n, y),
+            'D' => self.move_cursor(x.min(self.width() - 1).saturating_sub(n), y),
+            // Cursor to the start of the next line and the previous line.
+            'E' => self.move_cursor(0, (y + n).min(self.region_bottom_for(y))),
+            'F' => self.move_cursor(0, y.saturating_sub(n).max(self.region_top_for(y))),
+            // Cursor to column.
+            'G' | '`' => self.move_cursor(n - 1, y),
+            // Cursor to line.
+            'd' => self.move_cursor(x, n - 1),
+            // Cursor position. Note that the line comes first.
+            'H' | 'f' => self.move_cursor(seq.param(1, 1) as usize - 1, n - 1),
+            // Erase in display.
+            'J' => match seq.param(0, 0) {
+                0 => {
+                    self.erase_cells(y, x, self.width());
+                    self.erase_lines(y + 1, self.height());
+                },
+                1 => {
+                    self.erase_lines(0, y);
+                    self.erase_cells(y, 0, x + 1);
+                },
+                2 => self.erase_lines(0, self.height()),
+                // Like xterm, this erases the scrollback, but not the screen.
+                3 => self.scrollback.clear(),
+                _ => {},
+            },
+            // Erase in line.
+            'K' => match seq.param(0, 0) {
+                0 => self.erase_cells(y, x, self.width()),
+                1 => self.erase_cells(y, 0, x + 1),
+                2 => self.erase_cells(y, 0, self.width()),
+                _ => {},
+            },
+            // Erase characters.
+            'X' => self.erase_cells(y, x, x + n),
+            // Insert and delete lines, which only works inside of the scroll region.
+            'L' | 'M' => {
+                let (top, bottom) = self.scroll_region;
+                if y < top || y > bottom {
+                    return;
+                }
+
+                if seq.final_char == 'L' {
+                    self.scroll_down_region(y, bottom, n);
+                } else {
+                    self.scroll_up_region(y, bottom, n);
+                }
+                self.cursor.0 = 0;
+            },
+            // Insert and delete characters, shifting the rest of the line.
+            '@' => self.insert_cells(n),
+            'P' => self.delete_cells(n),
+            // Tab forward and backward.
+            'I' => self.tab_forward(n),
+            'Z' => self.tab_backward(n),
+            // Clear the tab stop at the cursor, or all of them.
+            'g' => match seq.param(0, 0) {
+                0 => {
+                    let x = x.min(self.width() - 1);
+                    self.tab_stops[x] = false;
+                },
+                3 => {
+                    for stop in self.tab_stops.iter_mut() {
+                        *stop = false;
+                    }
+                },
+                _ => {},
+            },
+            // Scroll up and down.
+            'S' => self.scroll_up(n),
+            'T' => self.scroll_down(n),
+            // Set scroll region.
+            'r' => {
+                let top = seq.param(0, 1) as usize - 1;
+                let bottom = (seq.param(1, self.height() as u16) as usize - 1).min(self.height() - 1);
+                if top < bottom {
+                    self.scroll_region = (top, bottom);
+                    self.move_cursor(0, 0);
+                }
+            },
+            // Save and restore the cursor.
+            's' => self.saved_cursor = (self.cursor, self.style),
+            'u' => {
+                let ((x, y), style) = self.saved_cursor;
+                self.move_cursor(x, y);
+                self.style = style;
+            },
+            // Select graphic rendition.
+            'm' => self.select_graphic_rendition(seq),
+            _ => {},
+        }
+    }
+
+    /// The top line the cursor can move up to without leaving the scroll region.
+    /// If the cursor is already above the scroll region, it can go all the way up.
+    fn region_top_for(&self, y: usize) -> usize {
+        if y >= self.scroll_region.0 { self.scroll_region.0 } else { 0 }
+    }
+
+    /// The bottom line the cursor can move down to without leaving the scroll region.
+    fn region_bottom_for(&self, y: usize) -> usize {
+        if y <= self.scroll_region.1 { self.scroll_region.1 } else { self.height() - 1 }
+    }
+
+    fn select_graphic_rendition(&mut self, seq: &ControlSequence) {
+        // No parameters at all means the same thing as a reset.
+        if seq.params().is_empty() {
+            self.style = self.default_style;
+            return;
+        }
+
+        // Sub-parameters only mean anything to extended colors; otherwise we go by the first parameter.
+        let mut groups = seq.groups();
+        while let Some(group) = groups.next() {
+            let p = group[0];
+            let attrs = &mut self.style.attrs;
+            match p {
+                0 => self.style = self.default_style,
+                1 => attrs.insert(Attributes::BOLD),
+                2 => attrs.insert(Attributes::DIM),
+                4 => attrs.insert(Attributes::UNDERLINE),
+                7 => attrs.insert(Attributes::INVERSE),
+                22 => attrs.remove(Attributes::BOLD | Attributes::DIM),
+                24 => attrs.remove(Attributes::UNDERLINE),
+                27 => attrs.remove(Attributes::INVERSE),
+                30..=37 => self.style.fg = self.palette.color((p - 30) as u8),
+                38 => if let Some(c) = extended_color(&self.palette, group, &mut groups) { self.style.fg = c },
+                39 => self.style.fg = self.default_style.fg,
+                40..=47 => self.style.bg = self.palette.color((p - 40) as u8),
+                48 => if let Some(c) = extended_color(&self.palette, group, &mut groups) { self.style.bg = c },
+                49 => self.style.bg = self.default_style.bg,
+                90..=97 => self.style.fg = self.palette.color((p - 90 + 8) as u8),
+                100..=107 => self.style.bg = self.palette.color((p - 100 + 8) as u8),
+                _ => {},
+            }
+        }
+    }
+
+    pub fn perform(&mut self, action: Action) {
+        // There's nowhere to put the cursor on an empty screen.
+        if self.width() == 0 || self.height() == 0 {
+            return;
+        }
+
+        match action {
+            Action::Print(c) => self.print(c),
+            Action::Execute(c) => self.execute(c),
+            Action::Escape { intermediate, final_char } => self.escape(intermediate, final_char),
+            Action::Csi(seq) => self.csi(seq),
+        }
+    }
+}
+
+/// Parse the rest of an extended color after `38` or `48`:
+/// either `5;n` for the 256-color palette, or `2;r;g;b` for truecolor.
+/// The colon-separated forms (`38:5:n` and `38:2:r:g:b`) are all one group instead,
+/// and truecolor may have a color space ID before the components (`38:2:id:r:g:b`), which we ignore.
+fn extended_color<'a>(palette: &Palette, group: &[u16], groups: &mut impl Iterator<Item = &'a [u16]>)
+    -> Option<RGB> {
+    if group.len() > 1 {
+        let channel = |c: u16| c.min(255) as u8;
+        return match group[1..] {
+            [5, n, ..] => Some(palette.color(channel(n))),
+            [2, _, r, g, b, ..] | [2, r, g, b] => Some(RGB::new(channel(r), channel(g), channel(b))),
+            _ => None,
+        };
+    }
+
+    // Otherwise, each part of the color is a parameter of its own.
+    let mut params = groups.map(|group| group[0]);
+    match params.next()? {
+        5 => Some(palette.color(params.next()?.min(255) as u8)),
+        2 => {
+            let r = params.next()?.min(255) as u8;
+            let g = params.next()?.min(255) as u8;
+            let b = params.next()?.min(255) as u8;
+            Some(RGB::new(r, g, b))
+        },
+        _ => None,
+    }
+}
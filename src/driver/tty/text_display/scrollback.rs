@@ -0,0 +1,100 @@
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use crate::driver::text_display::Cell;
+
+/// A single physical line of a terminal, i.e. one row of cells.
+#[derive(Clone)]
+pub struct Line {
+    pub cells: Vec<Cell>,
+    /// Whether the text on this line continues onto the next line
+    /// because it was too long to fit, rather than because of a line break.
+    /// We need to know this to re-wrap the text when the width of the terminal changes.
+    pub wrapped: bool,
+}
+
+impl Line {
+    pub fn blank(width: usize, blank: Cell) -> Line {
+        let mut cells = Vec::new();
+        cells.resize(width, blank);
+        Line { cells: cells, wrapped: false }
+    }
+
+    /// The cells of this line which are actually part of the text on it.
+    /// Unless the line wraps, any empty cells at the end of the line aren't part of the text;
+    /// they're just there because the line is shorter than the terminal is wide.
+    pub fn content(&self) -> &[Cell] {
+        if self.wrapped {
+            return &self.cells;
+        }
+
+        let len = self.cells.iter().rposition(|c| c.ch != '\u{0}').map_or(0, |i| i + 1);
+        &self.cells[..len]
+    }
+
+    /// The cells of this line without any `blank` cells at the end, unless the line wraps.
+    /// Unlike `content`, blank cells which look different from `blank` (e.g. erased with a background color) are kept.
+    pub fn trimmed(&self, blank: Cell) -> &[Cell] {
+        if self.wrapped {
+            return &self.cells;
+        }
+
+        let len = self.cells.iter().rposition(|&c| c != blank).map_or(0, |i| i + 1);
+        &self.cells[..len]
+    }
+}
+
+/// Lines which have scrolled off of the top of the screen, oldest first.
+///
+/// The scrollback holds a fixed number of lines; once it's full,
+/// the oldest line is thrown away for every new line which is added.
+/// Lines are stored exactly as they were displayed (i.e. already wrapped),
+/// so that the scrollback can be displayed without any extra work.
+pub struct Scrollback {
+    lines: VecDeque<Line>,
+    capacity: usize,
+    /// What the display shows past the end of a line that's shorter than the display is wide.
+    blank: Cell,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize, blank: Cell) -> Scrollback {
+        Scrollback {
+            lines: VecDeque::with_capacity(capacity),
+            capacity: capacity,
+            blank: blank,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// Get a line, where 0 is the oldest line.
+    pub fn get(&self, i: usize) -> &Line {
+        &self.lines[i]
+    }
+
+    /// Add a line to the scrollback, throwing away the oldest line if it's full.
+    pub fn push(&mut self, line: &Line) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+        }
+
+        // Empty cells at the end of the line take up as much space as anything else,
+        // so we don't keep the ones that look the same as the space past the end of the line.
+        self.lines.push_back(Line { cells: line.trimmed(self.blank).to_vec(), wrapped: line.wrapped });
+    }
+
+    /// Remove every line from the scrollback, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = Line> + '_ {
+        self.lines.drain(..)
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
@@ -1,10 +1,20 @@
-#[derive(Copy, Clone)]
+pub mod hsv;
+pub mod palette;
+pub mod srgb;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
 pub struct RGB {
     r: u8,
     g: u8,
     b: u8
 }
 
+impl RGB {
+    pub const fn new(r: u8, g: u8, b: u8) -> RGB {
+        RGB { r: r, g: g, b: b }
+    }
+}
+
 pub trait Color: Copy {
     fn r(&self) -> u8;
     fn g(&self) -> u8;
@@ -25,5 +35,214 @@ impl Color for RGB {
     fn b(&self) -> u8 { self.b }
 }
 
+/// A color with an alpha channel, from 0 (transparent) to 255 (opaque).
+/// The color channels are *not* premultiplied by the alpha (see `PremultipliedRGBA`),
+/// which is what images and people usually expect.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct RGBA {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+impl RGBA {
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> RGBA {
+        RGBA { r: r, g: g, b: b, a: a }
+    }
+
+    pub fn a(&self) -> u8 { self.a }
+
+    /// Draw this color on top of an opaque color.
+    pub fn over(&self, bg: impl Color) -> RGB {
+        blend(bg, *self, self.a)
+    }
+
+    pub fn premultiply(&self) -> PremultipliedRGBA {
+        PremultipliedRGBA {
+            r: mul(self.r, self.a),
+            g: mul(self.g, self.a),
+            b: mul(self.b, self.a),
+            a: self.a,
+        }
+    }
+}
+
+impl Color for RGBA {
+    fn r(&self) -> u8 { self.r }
+    fn g(&self) -> u8 { self.g }
+    fn b(&self) -> u8 { self.b }
+}
+
+impl From<RGB> for RGBA {
+    fn from(color: RGB) -> RGBA {
+        RGBA::new(color.r, color.g, color.b, 0xFF)
+    }
+}
+
+/// A color with an alpha channel, where the color channels have already been
+/// multiplied by the alpha (so no channel is ever greater than the alpha).
+///
+/// Compositing premultiplied colors is cheaper than straight alpha,
+/// and unlike straight alpha, layering translucent colors on top of each other
+/// gives the same result no matter how the layers are grouped,
+/// so it's the better choice for anything with more than one layer.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct PremultipliedRGBA {
+    r: u8,
+    g: u8,
+    b: u8,
+    a: u8,
+}
+
+impl PremultipliedRGBA {
+    pub const TRANSPARENT: PremultipliedRGBA = PremultipliedRGBA { r: 0, g: 0, b: 0, a: 0 };
+
+    pub fn r(&self) -> u8 { self.r }
+    pub fn g(&self) -> u8 { self.g }
+    pub fn b(&self) -> u8 { self.b }
+    pub fn a(&self) -> u8 { self.a }
+
+    /// Layer this color on top of another (the Porter-Duff "over" operator).
+    pub fn over(&self, below: PremultipliedRGBA) -> PremultipliedRGBA {
+        let rest = 255 - self.a;
+        PremultipliedRGBA {
+            r: self.r + mul(below.r, rest),
+            g: self.g + mul(below.g, rest),
+            b: self.b + mul(below.b, rest),
+            a: self.a + mul(below.a, rest),
+        }
+    }
+
+    /// Layer this color on top of an opaque color.
+    pub fn over_opaque(&self, bg: impl Color) -> RGB {
+        let rest = 255 - self.a;
+        RGB {
+            r: self.r + mul(bg.r(), rest),
+            g: self.g + mul(bg.g(), rest),
+            b: self.b + mul(bg.b(), rest),
+        }
+    }
+
+    pub fn unpremultiply(&self) -> RGBA {
+        if self.a == 0 {
+            return RGBA::new(0, 0, 0, 0);
+        }
+
+        let div = |c: u8| ((c as u32 * 255 + self.a as u32 / 2) / self.a as u32).min(255) as u8;
+        RGBA::new(div(self.r), div(self.g), div(self.b), self.a)
+    }
+}
+
+impl From<RGBA> for PremultipliedRGBA {
+    fn from(color: RGBA) -> PremultipliedRGBA {
+        color.premultiply()
+    }
+}
+
+/// Multiply two values as if they were fractions of 255, rounding to the nearest value.
+fn mul(a: u8, b: u8) -> u8 {
+    // This is the usual trick for dividing by 255 exactly, without an actual division.
+    let x = a as u32 * b as u32 + 128;
+    ((x + (x >> 8)) >> 8) as u8
+}
+
 pub const COLOR_BLACK: RGB = RGB { r: 0x23, g: 0x23, b: 0x23 };
 pub const COLOR_WHITE: RGB = RGB { r: 0xFF, g: 0xFF, b: 0xFF };
+
+/// How the channels of a color are packed into a pixel in a display's memory.
+#[derive(Copy, Clone)]
+pub enum PixelFormat {
+    /// Byte 0 is red, byte 1 is green, byte 2 is blue, and byte 3 is reserved.
+    Rgb,
+    /// Byte 0 is blue, byte 1 is green, byte 2 is red, and byte 3 is reserved.
+    Bgr,
+    /// Each channel occupies exactly the bits which are set in its mask.
+    /// The reserved mask covers any bits which are part of the pixel but not used for color.
+    Bitmask { red: u32, green: u32, blue: u32, reserved: u32 },
+}
+
+/// A color which has already been encoded into some display's native pixel format.
+///
+/// Encoding a color isn't free (especially for bitmask formats),
+/// so displays should encode each color once and then copy the native pixel around.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct NativePixel(pub u32);
+
+/// Scale an 8-bit channel to fit into the bits set in `mask`.
+fn encode_channel(value: u8, mask: u32) -> u32 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    // Round to the nearest representable value rather than truncating,
+    // so that e.g. 0xFF is still full intensity in a 5-bit channel.
+    let scaled = (value as u64 * max + 127) / 255;
+    (scaled as u32) << shift
+}
+
+/// The inverse of `encode_channel`.
+fn decode_channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let value = ((pixel & mask) >> shift) as u64;
+    ((value * 255 + max / 2) / max) as u8
+}
+
+impl PixelFormat {
+    /// The number of bytes each pixel occupies in memory.
+    pub fn bytes_per_pixel(&self) -> usize {
+        match self {
+            PixelFormat::Rgb | PixelFormat::Bgr => 4,
+            PixelFormat::Bitmask { red, green, blue, reserved } => {
+                let bits = 32 - (red | green | blue | reserved).leading_zeros() as usize;
+                num_integer::div_ceil(bits, 8).max(1)
+            },
+        }
+    }
+
+    pub fn encode(&self, color: impl Color) -> NativePixel {
+        let (r, g, b) = (color.r() as u32, color.g() as u32, color.b() as u32);
+        NativePixel(match *self {
+            PixelFormat::Rgb => r | g << 8 | b << 16,
+            PixelFormat::Bgr => b | g << 8 | r << 16,
+            PixelFormat::Bitmask { red, green, blue, .. } =>
+                encode_channel(color.r(), red)
+                    | encode_channel(color.g(), green)
+                    | encode_channel(color.b(), blue),
+        })
+    }
+
+    /// Convert a pixel read back from the display into a color.
+    /// Channels with fewer than 8 bits are scaled back up, so this is only
+    /// the exact inverse of `encode` if every channel has at least 8 bits.
+    pub fn decode(&self, pixel: NativePixel) -> RGB {
+        let pixel = pixel.0;
+        match *self {
+            PixelFormat::Rgb => RGB::new(pixel as u8, (pixel >> 8) as u8, (pixel >> 16) as u8),
+            PixelFormat::Bgr => RGB::new((pixel >> 16) as u8, (pixel >> 8) as u8, pixel as u8),
+            PixelFormat::Bitmask { red, green, blue, .. } =>
+                RGB::new(decode_channel(pixel, red), decode_channel(pixel, green), decode_channel(pixel, blue)),
+        }
+    }
+}
+
+/// Mix two colors, where `alpha` is how much of `fg` to use,
+/// from 0 (entirely `bg`) to 255 (entirely `fg`).
+pub fn blend(bg: impl Color, fg: impl Color, alpha: u8) -> RGB {
+    let mix = |bg: u8, fg: u8| -> u8 {
+        let alpha = alpha as u32;
+        ((bg as u32 * (255 - alpha) + fg as u32 * alpha + 127) / 255) as u8
+    };
+    RGB {
+        r: mix(bg.r(), fg.r()),
+        g: mix(bg.g(), fg.g()),
+        b: mix(bg.b(), fg.b()),
+    }
+}
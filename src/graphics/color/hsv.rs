@@ -0,0 +1,85 @@
+use crate::graphics::color::{Color, RGB};
+
+/// A color as a hue, saturation, and value (brightness),
+/// which is much easier to pick colors in or make variations of a color with
+/// (e.g. the same hue, but darker) than RGB.
+///
+/// Everything is integers (and hues are whole degrees),
+/// so converting to HSV and back may be off by a little in each channel.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct HSV {
+    /// The angle around the color wheel in degrees, from 0 up to (but not including) 360,
+    /// where 0 is red, 120 is green, and 240 is blue.
+    pub h: u16,
+    /// 0 is gray, and 255 is the most intense color with this hue and value.
+    pub s: u8,
+    /// 0 is black, and 255 is the brightest color with this hue and saturation.
+    pub v: u8,
+}
+
+impl HSV {
+    pub const fn new(h: u16, s: u8, v: u8) -> HSV {
+        HSV { h: h % 360, s: s, v: v }
+    }
+
+    pub fn from_rgb(color: impl Color) -> HSV {
+        let (r, g, b) = (color.r() as i32, color.g() as i32, color.b() as i32);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        if delta == 0 {
+            // Grays have no hue at all, so we just call it red.
+            return HSV::new(0, 0, max as u8);
+        }
+
+        // Which 60-degree sector of the color wheel we're in depends on which channel is biggest,
+        // and how far we are through the sector depends on the other two.
+        let hue = |start: i32, a: i32, b: i32| start + (120 * (a - b) + delta).div_euclid(2 * delta);
+        let h = if max == r {
+            hue(0, g, b)
+        } else if max == g {
+            hue(120, b, r)
+        } else {
+            hue(240, r, g)
+        };
+        let s = (255 * delta + max / 2) / max;
+        HSV::new(h.rem_euclid(360) as u16, s as u8, max as u8)
+    }
+
+    pub fn to_rgb(&self) -> RGB {
+        let v = self.v as u32;
+        if self.s == 0 {
+            return RGB::new(self.v, self.v, self.v);
+        }
+
+        let sector = self.h / 60;
+        // How far through the sector we are, from 0 to 59.
+        let f = (self.h % 60) as u32;
+        let s = self.s as u32;
+        // The smallest channel, and the middle channel on its way down or up respectively.
+        let p = ((v * (255 - s) + 127) / 255) as u8;
+        let q = ((v * (255 * 60 - s * f) + 255 * 30) / (255 * 60)) as u8;
+        let t = ((v * (255 * 60 - s * (60 - f)) + 255 * 30) / (255 * 60)) as u8;
+        let v = self.v;
+        match sector {
+            0 => RGB::new(v, t, p),
+            1 => RGB::new(q, v, p),
+            2 => RGB::new(p, v, t),
+            3 => RGB::new(p, q, v),
+            4 => RGB::new(t, p, v),
+            _ => RGB::new(v, p, q),
+        }
+    }
+}
+
+impl From<RGB> for HSV {
+    fn from(color: RGB) -> HSV {
+        HSV::from_rgb(color)
+    }
+}
+
+impl From<HSV> for RGB {
+    fn from(color: HSV) -> RGB {
+        color.to_rgb()
+    }
+}
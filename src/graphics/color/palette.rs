@@ -0,0 +1,86 @@
+use crate::graphics::color::RGB;
+
+/// The sixteen colors which terminals let programs pick by name:
+/// the eight standard ANSI colors, followed by their bright variants.
+/// Their values are the index of each color in a palette.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AnsiColor {
+    Black = 0,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    White,
+    BrightBlack,
+    BrightRed,
+    BrightGreen,
+    BrightYellow,
+    BrightBlue,
+    BrightMagenta,
+    BrightCyan,
+    BrightWhite,
+}
+
+/// A 256-color palette, as used by terminal escape sequences.
+///
+/// Only the first 16 colors (see `AnsiColor`) differ between palettes.
+/// The rest are always a 6x6x6 color cube followed by a ramp of 24 grays,
+/// which programs rely on being the same everywhere.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Palette {
+    base: [RGB; 16],
+}
+
+impl Palette {
+    /// The default colors of xterm.
+    pub const XTERM: Palette = Palette::new([
+        RGB::new(0x00, 0x00, 0x00), RGB::new(0xCD, 0x00, 0x00),
+        RGB::new(0x00, 0xCD, 0x00), RGB::new(0xCD, 0xCD, 0x00),
+        RGB::new(0x00, 0x00, 0xEE), RGB::new(0xCD, 0x00, 0xCD),
+        RGB::new(0x00, 0xCD, 0xCD), RGB::new(0xE5, 0xE5, 0xE5),
+        RGB::new(0x7F, 0x7F, 0x7F), RGB::new(0xFF, 0x00, 0x00),
+        RGB::new(0x00, 0xFF, 0x00), RGB::new(0xFF, 0xFF, 0x00),
+        RGB::new(0x5C, 0x5C, 0xFF), RGB::new(0xFF, 0x00, 0xFF),
+        RGB::new(0x00, 0xFF, 0xFF), RGB::new(0xFF, 0xFF, 0xFF),
+    ]);
+
+    /// The colors of the VGA text mode, which is what the Linux console uses.
+    pub const VGA: Palette = Palette::new([
+        RGB::new(0x00, 0x00, 0x00), RGB::new(0xAA, 0x00, 0x00),
+        RGB::new(0x00, 0xAA, 0x00), RGB::new(0xAA, 0x55, 0x00),
+        RGB::new(0x00, 0x00, 0xAA), RGB::new(0xAA, 0x00, 0xAA),
+        RGB::new(0x00, 0xAA, 0xAA), RGB::new(0xAA, 0xAA, 0xAA),
+        RGB::new(0x55, 0x55, 0x55), RGB::new(0xFF, 0x55, 0x55),
+        RGB::new(0x55, 0xFF, 0x55), RGB::new(0xFF, 0xFF, 0x55),
+        RGB::new(0x55, 0x55, 0xFF), RGB::new(0xFF, 0x55, 0xFF),
+        RGB::new(0x55, 0xFF, 0xFF), RGB::new(0xFF, 0xFF, 0xFF),
+    ]);
+
+    /// A palette with the given 16 named colors, in the order of `AnsiColor`.
+    pub const fn new(base: [RGB; 16]) -> Palette {
+        Palette { base: base }
+    }
+
+    pub fn ansi(&self, color: AnsiColor) -> RGB {
+        self.base[color as usize]
+    }
+
+    pub fn color(&self, index: u8) -> RGB {
+        match index {
+            0..=15 => self.base[index as usize],
+            // A 6x6x6 color cube.
+            16..=231 => {
+                let level = |i: u8| if i == 0 { 0 } else { 55 + i * 40 };
+                let i = index - 16;
+                RGB::new(level(i / 36), level(i / 6 % 6), level(i % 6))
+            },
+            // A grayscale ramp, excluding black and white, which are already in the cube.
+            232..=255 => {
+                let gray = 8 + (index - 232) * 10;
+                RGB::new(gray, gray, gray)
+            },
+        }
+    }
+}
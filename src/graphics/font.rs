@@ -1,17 +1,69 @@
+pub mod bdf;
+pub mod bold;
+pub mod fallback;
+pub mod hex_box;
+pub mod psf;
+pub mod scaled;
+
 use pc_screen_font;
 
-// Note that currently the Font and Glyph traits are fairly specialized to PSF.
-// They will certainly have to be modified to support other types of fonts,
-// but they *work* for now, and that's what's important/
+// These traits were originally written with PSF in mind, but they're general enough
+// for any bitmap font where every character fits in the same bounding box
+// (which is what a terminal needs anyway); BDF fonts (see `bdf`) are supported too.
 
+// Fonts used to have an associated glyph type, but that made it impossible
+// to combine fonts of different formats (see `fallback`), so glyphs are always `dyn` now.
 pub trait Font {
-    // Once Rust supports existential types, this needs to be an existential type.
-    type Glyph: Glyph;
-
     /// The size, in pixels, of the bounding box of each glyph in this font.
     fn bounding_box(&self) -> (usize, usize);
 
-    fn lookup<'a>(&'a self, ch: char) -> Option<&'a Self::Glyph>;
+    fn lookup<'a>(&'a self, ch: char) -> Option<PlacedGlyph<'a>>;
+}
+
+/// A glyph as it's placed in a particular font's bounding box.
+///
+/// Usually this is just the glyph itself, but a font made of other fonts
+/// may have to move glyphs around to line them up with each other,
+/// and it can't change the glyphs themselves.
+#[derive(Copy, Clone)]
+pub struct PlacedGlyph<'a> {
+    glyph: &'a dyn Glyph,
+    /// Added to the glyph's own offset.
+    offset: (usize, usize),
+}
+
+impl PlacedGlyph<'_> {
+    pub fn new<'a>(glyph: &'a dyn Glyph) -> PlacedGlyph<'a> {
+        PlacedGlyph::with_offset(glyph, (0, 0))
+    }
+
+    pub fn with_offset<'a>(glyph: &'a dyn Glyph, offset: (usize, usize)) -> PlacedGlyph<'a> {
+        PlacedGlyph {
+            glyph: glyph,
+            offset: offset,
+        }
+    }
+
+    /// Move the glyph even further.
+    pub fn moved_by(self, offset: (usize, usize)) -> Self {
+        PlacedGlyph {
+            glyph: self.glyph,
+            offset: (self.offset.0 + offset.0, self.offset.1 + offset.1),
+        }
+    }
+}
+
+impl Glyph for PlacedGlyph<'_> {
+    fn width(&self) -> usize { self.glyph.width() }
+    fn height(&self) -> usize { self.glyph.height() }
+
+    fn offset(&self) -> (usize, usize) {
+        let (x, y) = self.glyph.offset();
+        (x + self.offset.0, y + self.offset.1)
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool { self.glyph.get(x, y) }
+    fn coverage(&self, x: usize, y: usize) -> u8 { self.glyph.coverage(x, y) }
 }
 
 pub trait Glyph {
@@ -23,17 +75,30 @@ pub trait Glyph {
     /// This may be a different size than the font's bounding box.
     fn height(&self) -> usize;
 
-    // TODO: Support glyph offsets relative to the font bounding box.
+    /// Where the top-left corner of this glyph goes, relative to the top-left corner
+    /// of the font's bounding box. Glyphs which don't fill the whole bounding box
+    /// (e.g. punctuation in BDF fonts) are only as big as they need to be, and offset into place.
+    fn offset(&self) -> (usize, usize) { (0, 0) }
 
     /// Check whether an individual pixel of this glyph is set.
     /// This function will panic if `x` and `y` are outside the width and height of this glyph.
     fn get(&self, x: usize, y: usize) -> bool;
+
+    /// How much of an individual pixel is covered by this glyph,
+    /// from 0 (not at all) to 255 (entirely), for anti-aliased glyphs.
+    /// For ordinary bitmap glyphs, pixels are either entirely covered or not at all.
+    /// This function will panic if `x` and `y` are outside the width and height of this glyph.
+    fn coverage(&self, x: usize, y: usize) -> u8 {
+        if self.get(x, y) { 0xFF } else { 0 }
+    }
 }
 
 impl Font for pc_screen_font::Font {
-    type Glyph = pc_screen_font::Glyph;
     fn bounding_box(&self) -> (usize, usize) { self.bounding_box() }
-    fn lookup<'a>(&'a self, ch: char) -> Option<&'a Self::Glyph> { self.lookup(ch) }
+
+    fn lookup<'a>(&'a self, ch: char) -> Option<PlacedGlyph<'a>> {
+        self.lookup(ch).map(|glyph| PlacedGlyph::new(glyph))
+    }
 }
 
 impl Glyph for pc_screen_font::Glyph {
@@ -0,0 +1,336 @@
+// A parser for the Glyph Bitmap Distribution Format (BDF), the X11 bitmap font format.
+// A lot of bitmap fonts (including many with good CJK and symbol coverage) are only distributed as BDF.
+//
+// A BDF font is a text file, which looks roughly like this:
+//
+//     STARTFONT 2.1
+//     FONTBOUNDINGBOX 6 13 0 -3
+//     CHARS 1
+//     STARTCHAR A
+//     ENCODING 65
+//     BBX 5 7 0 0
+//     BITMAP
+//     20
+//     50
+//     ...
+//     ENDCHAR
+//     ENDFONT
+//
+// Unlike PSF, each glyph has its own bounding box, which is positioned relative to the baseline,
+// so we have to work out where each glyph goes relative to the font's bounding box.
+// See Adobe's "Glyph Bitmap Distribution Format (BDF) Specification", version 2.2.
+//
+// We also support the grayscale extension from BDF 2.3, where the `SIZE` line has a fourth value,
+// the number of bits per pixel (1, 2, 4, or 8), and each pixel is a level of coverage
+// rather than just on or off. This is how anti-aliased fonts are stored as BDF.
+
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use core::fmt;
+use core::str::SplitWhitespace;
+use crate::graphics::font::{Font, Glyph, PlacedGlyph};
+
+/// The console's fallback font, for everything Cozette (see `psf::COZETTE`) doesn't have.
+/// It's DejaVu Sans Mono, rendered to fit Cozette's cells, and anti-aliased so that it's legible that small.
+/// (See `assets/dejavu-attribution.txt`.)
+pub static DEJAVU_SANS_MONO: &[u8] = include_bytes!("../../../assets/dejavu-sans-mono.bdf");
+
+/// The reasons a BDF font may fail to load.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum BdfError {
+    /// The font doesn't start with `STARTFONT`, so it probably isn't a BDF font at all.
+    NotBdf,
+    /// The font has glyphs before saying how big its bounding box is.
+    MissingBoundingBox,
+    /// A glyph has a bitmap before saying how big its bounding box is.
+    MissingGlyphBoundingBox { line: usize },
+    /// A keyword is missing some of its values, they aren't numbers, or they're out of range
+    /// (e.g. a glyph is far bigger than the font's bounding box).
+    BadValue { line: usize },
+    /// A row of a bitmap isn't valid hexadecimal, or is too short for the glyph.
+    BadBitmap { line: usize },
+    /// The font has a number of bits per pixel other than 1, 2, 4, or 8.
+    UnsupportedDepth { line: usize },
+    /// The font ends in the middle of something.
+    UnexpectedEnd,
+}
+
+impl fmt::Display for BdfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BdfError::NotBdf =>
+                write!(f, "not a BDF font (missing STARTFONT)"),
+            BdfError::MissingBoundingBox =>
+                write!(f, "font has no FONTBOUNDINGBOX"),
+            BdfError::MissingGlyphBoundingBox { line } =>
+                write!(f, "line {}: glyph has no BBX", line),
+            BdfError::BadValue { line } =>
+                write!(f, "line {}: missing or invalid value", line),
+            BdfError::BadBitmap { line } =>
+                write!(f, "line {}: invalid bitmap row", line),
+            BdfError::UnsupportedDepth { line } =>
+                write!(f, "line {}: unsupported number of bits per pixel", line),
+            BdfError::UnexpectedEnd =>
+                write!(f, "font ends unexpectedly"),
+        }
+    }
+}
+
+pub struct BdfGlyph {
+    width: usize,
+    height: usize,
+    offset: (usize, usize),
+    /// The pixels of the glyph, most significant bits first;
+    /// each row is padded to a whole number of bytes.
+    bitmap: Vec<u8>,
+    bits_per_pixel: usize,
+    /// The number of bytes in each row of the bitmap.
+    stride: usize,
+    /// The number of columns and rows at the start of the bitmap which are cut off
+    /// because they stick out of the top or left of the font's bounding box.
+    skip: (usize, usize),
+}
+
+impl Glyph for BdfGlyph {
+    fn width(&self) -> usize { self.width }
+    fn height(&self) -> usize { self.height }
+    fn offset(&self) -> (usize, usize) { self.offset }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.coverage(x, y) >= 0x80
+    }
+
+    fn coverage(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width || y >= self.height {
+            panic!("Pixel out of bounds of glyph.");
+        }
+
+        let (x, y) = (x + self.skip.0, y + self.skip.1);
+        let bit = x * self.bits_per_pixel;
+        let byte = self.bitmap[y * self.stride + bit / 8];
+        let max = (1 << self.bits_per_pixel) - 1;
+        let value = (byte >> (8 - self.bits_per_pixel - bit % 8)) as usize & max;
+        (value * 0xFF / max) as u8
+    }
+}
+
+pub struct BdfFont {
+    bounding_box: (usize, usize),
+    /// Sorted by character, so we can binary search it.
+    glyphs: Vec<(char, BdfGlyph)>,
+}
+
+impl Font for BdfFont {
+    fn bounding_box(&self) -> (usize, usize) { self.bounding_box }
+
+    fn lookup<'a>(&'a self, ch: char) -> Option<PlacedGlyph<'a>> {
+        let i = self.glyphs.binary_search_by_key(&ch, |&(c, _)| c).ok()?;
+        Some(PlacedGlyph::new(&self.glyphs[i].1))
+    }
+}
+
+/// A bounding box in BDF's coordinates: the size, and the position of the bottom-left corner
+/// relative to the origin (which is on the baseline, with y increasing upwards).
+#[derive(Copy, Clone)]
+struct BoundingBox {
+    width: usize,
+    height: usize,
+    x: isize,
+    y: isize,
+}
+
+impl BoundingBox {
+    /// The position of the top edge, or `None` if it's too far away to represent.
+    fn top(&self) -> Option<isize> {
+        self.y.checked_add(isize::try_from(self.height).ok()?)
+    }
+}
+
+/// Glyphs can be at most this many times as wide or as tall as the font's bounding box.
+/// Double-width glyphs are only twice as wide, so anything much bigger than that is a broken font,
+/// and we'd rather reject it than try to work with an enormous bitmap.
+const MAX_GLYPH_SCALE: usize = 4;
+
+fn parse_bounding_box(values: &mut SplitWhitespace, line: usize) -> Result<BoundingBox, BdfError> {
+    let mut next = || values.next().ok_or(BdfError::BadValue { line: line });
+    let bad_value = |_| BdfError::BadValue { line: line };
+    Ok(BoundingBox {
+        width: next()?.parse().map_err(bad_value)?,
+        height: next()?.parse().map_err(bad_value)?,
+        x: next()?.parse().map_err(bad_value)?,
+        y: next()?.parse().map_err(bad_value)?,
+    })
+}
+
+/// The glyph that's currently being parsed.
+struct PartialGlyph {
+    /// The character this glyph is for, if it's for a character at all.
+    /// (Glyphs may have no encoding, or one that isn't Unicode, in which case we skip them.)
+    ch: Option<char>,
+    bounding_box: Option<BoundingBox>,
+    bitmap: Vec<u8>,
+    /// The number of rows of the bitmap still to be read, if we're reading the bitmap.
+    rows_left: Option<usize>,
+}
+
+impl BdfFont {
+    /// Parse a BDF font. The characters in the font are assumed to be encoded in Unicode
+    /// (i.e. the font's `CHARSET_REGISTRY` is `ISO10646`), which is true of nearly every font
+    /// that's likely to be used with this kernel.
+    pub fn parse(data: &[u8]) -> Result<BdfFont, BdfError> {
+        let mut font_box = None;
+        let mut bits_per_pixel = 1;
+        let mut glyphs = Vec::new();
+        let mut glyph: Option<PartialGlyph> = None;
+        let mut started = false;
+
+        for (i, line) in data.split(|&b| b == b'\n').enumerate() {
+            let number = i + 1;
+            // Comments and properties can be in any encoding, but nothing we care about can be,
+            // so anything which isn't UTF-8 is skipped (unless it's in the middle of a bitmap).
+            let line = core::str::from_utf8(line).unwrap_or("").trim();
+            let mut values = line.split_whitespace();
+            let keyword = values.next().unwrap_or("");
+
+            if !started {
+                if keyword != "STARTFONT" {
+                    return Err(BdfError::NotBdf);
+                }
+                started = true;
+                continue;
+            }
+
+            // Bitmaps are just hexadecimal numbers, one row per line, with no keyword.
+            if let Some(glyph) = glyph.as_mut() {
+                if let Some(rows_left) = glyph.rows_left.filter(|&rows| rows > 0) {
+                    let width = glyph.bounding_box.map_or(0, |bbx| bbx.width);
+                    parse_bitmap_row(line, width * bits_per_pixel, &mut glyph.bitmap)
+                        .ok_or(BdfError::BadBitmap { line: number })?;
+                    glyph.rows_left = Some(rows_left - 1);
+                    continue;
+                }
+            }
+
+            match keyword {
+                "FONTBOUNDINGBOX" => font_box = Some(parse_bounding_box(&mut values, number)?),
+                // The point size and resolution don't matter to us,
+                // since we always draw the glyphs at their actual size in pixels.
+                "SIZE" => if let Some(depth) = values.nth(3) {
+                    bits_per_pixel = match depth.parse() {
+                        Ok(depth @ 1) | Ok(depth @ 2) | Ok(depth @ 4) | Ok(depth @ 8) => depth,
+                        _ => return Err(BdfError::UnsupportedDepth { line: number }),
+                    };
+                },
+                "STARTCHAR" => glyph = Some(PartialGlyph {
+                    ch: None,
+                    bounding_box: None,
+                    bitmap: Vec::new(),
+                    rows_left: None,
+                }),
+                "ENCODING" => if let Some(glyph) = glyph.as_mut() {
+                    // Unencoded glyphs have an encoding of -1.
+                    let encoding: i64 = values.next().and_then(|v| v.parse().ok())
+                        .ok_or(BdfError::BadValue { line: number })?;
+                    glyph.ch = if encoding < 0 { None } else { core::char::from_u32(encoding as u32) };
+                },
+                "BBX" => if let Some(glyph) = glyph.as_mut() {
+                    let font_box = font_box.ok_or(BdfError::MissingBoundingBox)?;
+                    let bbx = parse_bounding_box(&mut values, number)?;
+                    if bbx.width > font_box.width.saturating_mul(MAX_GLYPH_SCALE)
+                        || bbx.height > font_box.height.saturating_mul(MAX_GLYPH_SCALE) {
+                        return Err(BdfError::BadValue { line: number });
+                    }
+                    glyph.bounding_box = Some(bbx);
+                },
+                "BITMAP" => if let Some(glyph) = glyph.as_mut() {
+                    let bbx = glyph.bounding_box.ok_or(BdfError::MissingGlyphBoundingBox { line: number })?;
+                    let size = stride(bbx.width, bits_per_pixel).and_then(|stride| stride.checked_mul(bbx.height))
+                        .ok_or(BdfError::BadValue { line: number })?;
+                    // Each byte of the bitmap takes two hex digits, so it can't be bigger than half the font,
+                    // however big the glyph claims to be.
+                    glyph.bitmap.reserve(size.min(data.len() / 2));
+                    glyph.rows_left = Some(bbx.height);
+                },
+                "ENDCHAR" => {
+                    let glyph = glyph.take().ok_or(BdfError::BadValue { line: number })?;
+                    let font_box = font_box.ok_or(BdfError::MissingBoundingBox)?;
+                    if let (Some(ch), Some(bbx)) = (glyph.ch, glyph.bounding_box) {
+                        if glyph.rows_left != Some(0) {
+                            return Err(BdfError::BadBitmap { line: number });
+                        }
+                        // We only find out the glyph doesn't fit here, so this is the line we blame.
+                        let placed = place_glyph(font_box, bbx, bits_per_pixel, glyph.bitmap)
+                            .ok_or(BdfError::BadValue { line: number })?;
+                        glyphs.push((ch, placed));
+                    }
+                },
+                "ENDFONT" => {
+                    let font_box = font_box.ok_or(BdfError::MissingBoundingBox)?;
+                    // The sort is stable, so if a character has more than one glyph, the first one wins.
+                    glyphs.sort_by_key(|&(ch, _)| ch);
+                    glyphs.dedup_by_key(|&mut (ch, _)| ch);
+                    return Ok(BdfFont {
+                        bounding_box: (font_box.width, font_box.height),
+                        glyphs: glyphs,
+                    });
+                },
+                // Everything else is either metadata we don't need, like the font's name,
+                // or metrics for proportional text layout, which a terminal can't make use of.
+                _ => {},
+            }
+        }
+
+        Err(if started { BdfError::UnexpectedEnd } else { BdfError::NotBdf })
+    }
+}
+
+/// The number of bytes in each row of a bitmap `width` pixels wide, or `None` if it's too many to count.
+fn stride(width: usize, bits_per_pixel: usize) -> Option<usize> {
+    Some(num_integer::div_ceil(width.checked_mul(bits_per_pixel)?, 8))
+}
+
+/// Parse one row of a bitmap, which is at least enough hex digits to hold `width` bits.
+fn parse_bitmap_row(line: &str, width: usize, bitmap: &mut Vec<u8>) -> Option<()> {
+    let bytes = num_integer::div_ceil(width, 8);
+    let digits = line.as_bytes();
+    if digits.len() < bytes * 2 {
+        return None;
+    }
+
+    for pair in digits[..bytes * 2].chunks(2) {
+        let high = (pair[0] as char).to_digit(16)?;
+        let low = (pair[1] as char).to_digit(16)?;
+        bitmap.push((high << 4 | low) as u8);
+    }
+    Some(())
+}
+
+/// Work out where a glyph goes within the font's bounding box.
+/// Anything sticking out of the top or left of the font's bounding box is cut off,
+/// since glyphs can't be drawn outside of their cell in that direction.
+/// Returns `None` if the glyph is so far away from the font's bounding box that we can't tell where it is,
+/// or if the bitmap isn't the size the glyph's bounding box says it is.
+fn place_glyph(font_box: BoundingBox, glyph_box: BoundingBox, bits_per_pixel: usize, bitmap: Vec<u8>)
+    -> Option<BdfGlyph> {
+    let stride = stride(glyph_box.width, bits_per_pixel)?;
+    if bitmap.len() != stride.checked_mul(glyph_box.height)? {
+        return None;
+    }
+
+    // Both boxes are positioned relative to the same origin, so the offset of the glyph
+    // from the left of the font's box is just the difference between their left edges.
+    let x = glyph_box.x.checked_sub(font_box.x)?;
+    let y = font_box.top()?.checked_sub(glyph_box.top()?)?;
+
+    let skip_x = if x < 0 { x.unsigned_abs() } else { 0 };
+    let skip_y = if y < 0 { y.unsigned_abs() } else { 0 };
+    Some(BdfGlyph {
+        width: glyph_box.width.saturating_sub(skip_x),
+        height: glyph_box.height.saturating_sub(skip_y),
+        offset: (x.max(0) as usize, y.max(0) as usize),
+        bitmap: bitmap,
+        bits_per_pixel: bits_per_pixel,
+        stride: stride,
+        skip: (skip_x, skip_y),
+    })
+}
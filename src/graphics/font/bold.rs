@@ -0,0 +1,42 @@
+use crate::graphics::font::Glyph;
+
+/// A glyph made bold by smearing it to the right, i.e. drawing it on top of itself
+/// shifted over by a few pixels.
+///
+/// Bitmap fonts rarely come with a bold variant, so this is the usual way to fake one.
+/// Most glyphs leave their last column empty, so the result usually still fits in the same cell.
+/// Doing this to the glyph itself (rather than drawing it twice) means partially-covered pixels
+/// of anti-aliased glyphs come out right where the two copies overlap.
+pub struct BoldGlyph<'g> {
+    glyph: &'g dyn Glyph,
+    shift: usize,
+}
+
+impl BoldGlyph<'_> {
+    pub fn new<'g>(glyph: &'g dyn Glyph, shift: usize) -> BoldGlyph<'g> {
+        BoldGlyph {
+            glyph: glyph,
+            shift: shift,
+        }
+    }
+}
+
+impl Glyph for BoldGlyph<'_> {
+    fn width(&self) -> usize { self.glyph.width() + self.shift }
+    fn height(&self) -> usize { self.glyph.height() }
+    fn offset(&self) -> (usize, usize) { self.glyph.offset() }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        self.coverage(x, y) >= 0x80
+    }
+
+    fn coverage(&self, x: usize, y: usize) -> u8 {
+        if x >= self.width() || y >= self.height() {
+            panic!("Pixel out of bounds of glyph.");
+        }
+
+        let original = if x < self.glyph.width() { self.glyph.coverage(x, y) } else { 0 };
+        let shifted = if x >= self.shift { self.glyph.coverage(x - self.shift, y) } else { 0 };
+        original.max(shifted)
+    }
+}
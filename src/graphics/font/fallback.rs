@@ -0,0 +1,51 @@
+use alloc::vec::Vec;
+use crate::graphics::font::{Font, PlacedGlyph};
+
+/// A font made of a list of other fonts, which are searched in order for each character.
+///
+/// No single font covers all of Unicode, so a small, good-looking font can come first,
+/// followed by larger fonts with better coverage (e.g. a CJK font, then a symbols font),
+/// and text is only drawn with replacement characters if none of them have a glyph for it.
+///
+/// The fonts may have different bounding boxes, but a terminal needs every cell to be
+/// the same size, so every font is centered in a single bounding box.
+/// Parts of glyphs which don't fit in it are cut off, so fonts should be roughly the same size.
+pub struct FallbackFont<'f> {
+    /// Each font along with where its bounding box goes in ours.
+    fonts: Vec<(&'f dyn Font, (usize, usize))>,
+    bounding_box: (usize, usize),
+}
+
+impl FallbackFont<'_> {
+    /// Combine fonts using the bounding box of the first font, so that text looks exactly the same
+    /// as it would with just the first font, except that characters it's missing are filled in.
+    pub fn new<'f>(fonts: &[&'f dyn Font]) -> FallbackFont<'f> {
+        let bounding_box = fonts.first().map_or((0, 0), |font| font.bounding_box());
+        FallbackFont::with_bounding_box(fonts, bounding_box)
+    }
+
+    /// Combine fonts, centering each of them in a bounding box of the given size.
+    pub fn with_bounding_box<'f>(fonts: &[&'f dyn Font], bounding_box: (usize, usize)) -> FallbackFont<'f> {
+        let (width, height) = bounding_box;
+        let fonts = fonts.iter().map(|&font| {
+            // Fonts which are too big are lined up with the top-left corner instead,
+            // since glyphs can't be moved up or left out of their bounding box.
+            let (font_width, font_height) = font.bounding_box();
+            let offset = (width.saturating_sub(font_width) / 2, height.saturating_sub(font_height) / 2);
+            (font, offset)
+        }).collect();
+
+        FallbackFont {
+            fonts: fonts,
+            bounding_box: bounding_box,
+        }
+    }
+}
+
+impl Font for FallbackFont<'_> {
+    fn bounding_box(&self) -> (usize, usize) { self.bounding_box }
+
+    fn lookup<'a>(&'a self, ch: char) -> Option<PlacedGlyph<'a>> {
+        self.fonts.iter().find_map(|&(font, offset)| font.lookup(ch).map(|glyph| glyph.moved_by(offset)))
+    }
+}
@@ -0,0 +1,121 @@
+use crate::graphics::font::Glyph;
+
+/// A tiny 3x5 font for the hexadecimal digits.
+/// Each row is three bits, with the most significant bit on the left.
+const HEX_DIGITS: [[u8; 5]; 16] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111], // 0
+    [0b010, 0b110, 0b010, 0b010, 0b111], // 1
+    [0b111, 0b001, 0b111, 0b100, 0b111], // 2
+    [0b111, 0b001, 0b111, 0b001, 0b111], // 3
+    [0b101, 0b101, 0b111, 0b001, 0b001], // 4
+    [0b111, 0b100, 0b111, 0b001, 0b111], // 5
+    [0b111, 0b100, 0b111, 0b101, 0b111], // 6
+    [0b111, 0b001, 0b001, 0b001, 0b001], // 7
+    [0b111, 0b101, 0b111, 0b101, 0b111], // 8
+    [0b111, 0b101, 0b111, 0b001, 0b111], // 9
+    [0b111, 0b101, 0b111, 0b101, 0b101], // A
+    [0b110, 0b101, 0b110, 0b101, 0b110], // B
+    [0b111, 0b100, 0b100, 0b100, 0b111], // C
+    [0b110, 0b101, 0b101, 0b101, 0b110], // D
+    [0b111, 0b100, 0b111, 0b100, 0b111], // E
+    [0b111, 0b100, 0b111, 0b100, 0b100], // F
+];
+
+const DIGIT_WIDTH: usize = 3;
+const DIGIT_HEIGHT: usize = 5;
+/// The space between digits, and between the digits and the box.
+const SPACING: usize = 1;
+
+/// A glyph for characters which a font doesn't have:
+/// a box containing the character's codepoint in hexadecimal, in two rows,
+/// the way many terminals and text editors display them.
+///
+/// Boxes too narrow for that (like the cells of 6x13 fonts) leave out the sides of the box
+/// and the space between the digits, and if the digits still don't fit, the box is drawn empty.
+pub struct HexBoxGlyph {
+    width: usize,
+    height: usize,
+    digits: [u8; 6],
+    /// The number of digits in each row (there are always two rows).
+    columns: usize,
+    /// The space between the digits in each row.
+    column_spacing: usize,
+    /// Whether the box has sides, or just a top and a bottom.
+    sides: bool,
+    /// The top-left corner of the digits, if they fit in the box.
+    origin: Option<(usize, usize)>,
+}
+
+impl HexBoxGlyph {
+    /// Create a glyph for `ch` which fills a box of the given size.
+    pub fn new(ch: char, size: (usize, usize)) -> HexBoxGlyph {
+        let (width, height) = size;
+        let codepoint = ch as u32;
+        // Codepoints in the BMP take four digits, and everything else takes six.
+        let columns = if codepoint > 0xFFFF { 3 } else { 2 };
+
+        let mut digits = [0; 6];
+        for (i, digit) in digits.iter_mut().take(columns * 2).enumerate() {
+            let shift = 4 * (columns * 2 - 1 - i);
+            *digit = ((codepoint >> shift) & 0xF) as u8;
+        }
+
+        // Normally, the digits are surrounded by the border of the box and a pixel of space.
+        // If that doesn't fit, we squeeze them together and right up against the top and bottom,
+        // which is just enough for four digits in a 6x13 box.
+        let digits_height = 2 * DIGIT_HEIGHT + SPACING;
+        let spaced_width = columns * (DIGIT_WIDTH + SPACING) - SPACING;
+        let border = 1 + SPACING;
+        let (column_spacing, sides, origin) =
+            if width >= spaced_width + 2 * border && height >= digits_height + 2 * border {
+                (SPACING, true, Some(((width - spaced_width) / 2, (height - digits_height) / 2)))
+            } else if width >= columns * DIGIT_WIDTH && height >= digits_height + 2 {
+                (0, false, Some(((width - columns * DIGIT_WIDTH) / 2, (height - digits_height) / 2)))
+            } else {
+                (SPACING, true, None)
+            };
+
+        HexBoxGlyph {
+            width: width,
+            height: height,
+            digits: digits,
+            columns: columns,
+            column_spacing: column_spacing,
+            sides: sides,
+            origin: origin,
+        }
+    }
+
+    /// Whether a pixel (relative to the top-left corner of the digits) is part of a digit.
+    fn get_digit_pixel(&self, x: usize, y: usize) -> bool {
+        let (column, digit_x) = (x / (DIGIT_WIDTH + self.column_spacing), x % (DIGIT_WIDTH + self.column_spacing));
+        let (row, digit_y) = (y / (DIGIT_HEIGHT + SPACING), y % (DIGIT_HEIGHT + SPACING));
+        if column >= self.columns || row >= 2 || digit_x >= DIGIT_WIDTH || digit_y >= DIGIT_HEIGHT {
+            return false;
+        }
+
+        let digit = self.digits[row * self.columns + column];
+        HEX_DIGITS[digit as usize][digit_y] & (0b100 >> digit_x) != 0
+    }
+}
+
+impl Glyph for HexBoxGlyph {
+    fn width(&self) -> usize { self.width }
+    fn height(&self) -> usize { self.height }
+
+    fn get(&self, x: usize, y: usize) -> bool {
+        if x >= self.width || y >= self.height {
+            panic!("Pixel out of bounds of glyph.");
+        }
+
+        if y == 0 || y == self.height - 1 || (self.sides && (x == 0 || x == self.width - 1)) {
+            return true;
+        }
+
+        match self.origin {
+            Some((origin_x, origin_y)) if x >= origin_x && y >= origin_y =>
+                self.get_digit_pixel(x - origin_x, y - origin_y),
+            _ => false,
+        }
+    }
+}
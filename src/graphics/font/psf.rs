@@ -0,0 +1,100 @@
+use core::fmt;
+
+/// The console font, built into the kernel so that we have a font before we have a filesystem.
+/// Cozette is a small, legible bitmap font with good Unicode coverage.
+/// (See `assets/cozette-attribution.txt`.)
+pub static COZETTE: &[u8] = include_bytes!("../../../assets/cozette.psf");
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// PSF1 fonts have 512 glyphs if this bit of the mode is set, and 256 otherwise.
+const PSF1_MODE_512: u8 = 0x01;
+
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xB5, 0x4A, 0x86];
+const PSF2_HEADER_SIZE: usize = 32;
+
+/// The reasons a PC Screen Font may fail to load.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PsfError {
+    /// The data doesn't start with the magic number of either version of PSF,
+    /// so it probably isn't a PSF font at all.
+    BadMagic,
+    /// The data ends before the end of the header or the glyphs.
+    Truncated { expected: usize, actual: usize },
+    /// The number of bytes per glyph doesn't match the size of the glyphs.
+    BadGlyphSize { width: usize, height: usize, glyph_size: usize },
+    /// The header is fine, but the rest of the font (i.e. the Unicode table) isn't.
+    Malformed,
+}
+
+impl fmt::Display for PsfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PsfError::BadMagic =>
+                write!(f, "not a PSF font (bad magic number)"),
+            PsfError::Truncated { expected, actual } =>
+                write!(f, "font is truncated (expected at least {} bytes, got {})", expected, actual),
+            PsfError::BadGlyphSize { width, height, glyph_size } =>
+                write!(f, "{}x{} glyphs can't be {} bytes each", width, height, glyph_size),
+            PsfError::Malformed =>
+                write!(f, "font has a malformed Unicode table"),
+        }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> usize {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes) as usize
+}
+
+/// Check that the header makes sense and that the data is long enough to hold all of the glyphs.
+///
+/// `pc_screen_font` doesn't tell us much about *why* a font failed to parse,
+/// so we check the usual suspects ourselves first.
+fn validate(data: &[u8]) -> Result<(), PsfError> {
+    let truncated = |expected| PsfError::Truncated { expected: expected, actual: data.len() };
+
+    if data.starts_with(&PSF1_MAGIC) {
+        if data.len() < PSF1_HEADER_SIZE {
+            return Err(truncated(PSF1_HEADER_SIZE));
+        }
+        // PSF1 glyphs are always 8 pixels wide, i.e. one byte per row.
+        let glyph_count = if data[2] & PSF1_MODE_512 != 0 { 512 } else { 256 };
+        let glyph_size = data[3] as usize;
+        let expected = PSF1_HEADER_SIZE + glyph_count * glyph_size;
+        if data.len() < expected {
+            return Err(truncated(expected));
+        }
+    } else if data.starts_with(&PSF2_MAGIC) {
+        if data.len() < PSF2_HEADER_SIZE {
+            return Err(truncated(PSF2_HEADER_SIZE));
+        }
+        let header_size = read_u32(data, 8);
+        let glyph_count = read_u32(data, 16);
+        let glyph_size = read_u32(data, 20);
+        let height = read_u32(data, 24);
+        let width = read_u32(data, 28);
+        // Each row of a glyph is padded to a whole number of bytes.
+        let row_size = num_integer::div_ceil(width, 8);
+        if row_size.checked_mul(height) != Some(glyph_size) {
+            return Err(PsfError::BadGlyphSize { width: width, height: height, glyph_size: glyph_size });
+        }
+        let expected = glyph_count.checked_mul(glyph_size)
+            .and_then(|size| size.checked_add(header_size.max(PSF2_HEADER_SIZE)))
+            .unwrap_or(usize::MAX);
+        if data.len() < expected {
+            return Err(truncated(expected));
+        }
+    } else {
+        return Err(PsfError::BadMagic);
+    }
+
+    Ok(())
+}
+
+/// Parse a PC Screen Font (either version 1 or 2).
+pub fn parse(data: &[u8]) -> Result<pc_screen_font::Font, PsfError> {
+    validate(data)?;
+    pc_screen_font::Font::parse(data).map_err(|_| PsfError::Malformed)
+}
@@ -0,0 +1,33 @@
+use crate::graphics::font::Glyph;
+
+/// A glyph blown up by an integer factor, so that each pixel of the original glyph
+/// becomes a `scale` by `scale` square of pixels.
+///
+/// Bitmap fonts are designed for a particular pixel size, and scaling them by anything
+/// other than a whole number makes them blurry or uneven, so this is the only kind of scaling we do.
+pub struct ScaledGlyph<'g> {
+    glyph: &'g dyn Glyph,
+    scale: usize,
+}
+
+impl ScaledGlyph<'_> {
+    pub fn new<'g>(glyph: &'g dyn Glyph, scale: usize) -> ScaledGlyph<'g> {
+        ScaledGlyph {
+            glyph: glyph,
+            scale: scale.max(1),
+        }
+    }
+}
+
+impl Glyph for ScaledGlyph<'_> {
+    fn width(&self) -> usize { self.glyph.width() * self.scale }
+    fn height(&self) -> usize { self.glyph.height() * self.scale }
+
+    fn offset(&self) -> (usize, usize) {
+        let (x, y) = self.glyph.offset();
+        (x * self.scale, y * self.scale)
+    }
+
+    fn get(&self, x: usize, y: usize) -> bool { self.glyph.get(x / self.scale, y / self.scale) }
+    fn coverage(&self, x: usize, y: usize) -> u8 { self.glyph.coverage(x / self.scale, y / self.scale) }
+}
@@ -0,0 +1,143 @@
+pub mod bmp;
+pub mod inflate;
+pub mod png;
+pub mod qoi;
+
+use alloc::vec::Vec;
+use core::fmt;
+use crate::driver::graphic_display::GraphicDisplay;
+use crate::graphics::color::RGBA;
+use crate::graphics::rect::Rect;
+
+// Images come from outside of the kernel (and may be corrupt or even malicious),
+// so the decoders never trust anything an image says about itself:
+// every read is bounds checked and every size calculation is checked for overflow,
+// so a bad image always turns into an error rather than a panic or a page fault.
+
+/// The most pixels we'll allocate for an image. Formats like QOI can describe
+/// an enormous image in a tiny file, so we can't rely on the size of the file to limit this.
+const MAX_PIXELS: usize = 4096 * 4096;
+
+/// The reasons an image may fail to decode.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// The data doesn't start with the signature of any format we support.
+    UnknownFormat,
+    /// The data ends before the end of the image.
+    Truncated,
+    /// The image is valid, but uses a feature we don't support (e.g. compression).
+    Unsupported(&'static str),
+    /// The image is larger than we're willing to allocate (see `MAX_PIXELS`), or has no pixels.
+    BadSize { width: usize, height: usize },
+    /// The image is corrupt.
+    Malformed(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnknownFormat =>
+                write!(f, "unknown image format"),
+            DecodeError::Truncated =>
+                write!(f, "image is truncated"),
+            DecodeError::Unsupported(feature) =>
+                write!(f, "unsupported image feature: {}", feature),
+            DecodeError::BadSize { width, height } =>
+                write!(f, "can't decode a {}x{} image", width, height),
+            DecodeError::Malformed(reason) =>
+                write!(f, "malformed image: {}", reason),
+        }
+    }
+}
+
+/// A decoded image, stored row by row from the top left.
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<RGBA>,
+}
+
+impl Image {
+    /// Panics if there isn't exactly one pixel for each position in the image.
+    pub fn new(width: usize, height: usize, pixels: Vec<RGBA>) -> Image {
+        assert_eq!(pixels.len(), width * height);
+        Image {
+            width: width,
+            height: height,
+            pixels: pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize { self.width }
+    pub fn height(&self) -> usize { self.height }
+    pub fn pixels(&self) -> &[RGBA] { &self.pixels }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<RGBA> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(self.pixels[y * self.width + x])
+    }
+
+    /// Draw the image on top of whatever is on the display, with its top-left corner at (`x`, `y`).
+    /// Any part of the image which is outside of the screen is cut off.
+    pub fn draw(&self, display: &mut dyn GraphicDisplay, x: isize, y: isize) {
+        display.blit_blended(&self.pixels, self.width, Rect::new(0, 0, self.width, self.height), x, y);
+    }
+
+    /// Draw the image in the middle of the display (e.g. for a boot logo).
+    pub fn draw_centered(&self, display: &mut dyn GraphicDisplay) {
+        let x = (display.width() as isize - self.width as isize) / 2;
+        let y = (display.height() as isize - self.height as isize) / 2;
+        self.draw(display, x, y);
+    }
+}
+
+/// Decode an image in any of the formats we support, based on its signature.
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if data.starts_with(bmp::SIGNATURE) {
+        bmp::decode(data)
+    } else if data.starts_with(png::SIGNATURE) {
+        png::decode(data)
+    } else if data.starts_with(qoi::SIGNATURE) {
+        qoi::decode(data)
+    } else {
+        Err(DecodeError::UnknownFormat)
+    }
+}
+
+/// Check that an image isn't too large (or empty), and return how many pixels it has.
+fn pixel_count(width: usize, height: usize) -> Result<usize, DecodeError> {
+    match width.checked_mul(height) {
+        Some(count) if count > 0 && count <= MAX_PIXELS => Ok(count),
+        _ => Err(DecodeError::BadSize { width: width, height: height }),
+    }
+}
+
+/// Read `len` bytes starting at `offset`.
+fn read_bytes(data: &[u8], offset: usize, len: usize) -> Result<&[u8], DecodeError> {
+    let end = offset.checked_add(len).ok_or(DecodeError::Truncated)?;
+    data.get(offset..end).ok_or(DecodeError::Truncated)
+}
+
+fn read_u8(data: &[u8], offset: usize) -> Result<u8, DecodeError> {
+    data.get(offset).copied().ok_or(DecodeError::Truncated)
+}
+
+fn read_u16_le(data: &[u8], offset: usize) -> Result<u16, DecodeError> {
+    let mut bytes = [0; 2];
+    bytes.copy_from_slice(read_bytes(data, offset, 2)?);
+    Ok(u16::from_le_bytes(bytes))
+}
+
+fn read_u32_le(data: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(read_bytes(data, offset, 4)?);
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u32_be(data: &[u8], offset: usize) -> Result<u32, DecodeError> {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(read_bytes(data, offset, 4)?);
+    Ok(u32::from_be_bytes(bytes))
+}
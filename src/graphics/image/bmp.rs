@@ -0,0 +1,146 @@
+// Windows bitmaps (BMP). We only support uncompressed bitmaps (optionally with bitfields),
+// which is what most tools write by default anyway.
+//
+// A bitmap is a 14-byte file header, followed by an info header (which comes in several versions,
+// each an extension of the last), an optional color table, and then the rows of pixels,
+// each padded to a multiple of 4 bytes and usually stored from the bottom up.
+
+use alloc::vec::Vec;
+use crate::graphics::color::RGBA;
+use crate::graphics::image::{self, DecodeError, Image};
+
+pub const SIGNATURE: &[u8] = b"BM";
+
+const FILE_HEADER_SIZE: usize = 14;
+/// The size of `BITMAPINFOHEADER`, the oldest header we support.
+/// (The even older `BITMAPCOREHEADER` from OS/2 is practically never seen.)
+const MIN_INFO_HEADER_SIZE: usize = 40;
+/// Headers at least this big (`BITMAPV3INFOHEADER` and later) have an alpha mask.
+const ALPHA_MASK_HEADER_SIZE: usize = 56;
+
+const BI_RGB: u32 = 0;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// Which bits of each pixel belong to each channel, for 16- and 32-bit bitmaps.
+#[derive(Copy, Clone)]
+struct Masks {
+    red: u32,
+    green: u32,
+    blue: u32,
+    /// If there's no alpha mask, the image is opaque.
+    alpha: u32,
+}
+
+impl Masks {
+    fn extract(&self, pixel: u32) -> RGBA {
+        let alpha = if self.alpha == 0 { 0xFF } else { extract_channel(pixel, self.alpha) };
+        RGBA::new(
+            extract_channel(pixel, self.red),
+            extract_channel(pixel, self.green),
+            extract_channel(pixel, self.blue),
+            alpha,
+        )
+    }
+}
+
+/// Scale the bits of `pixel` set in `mask` to a full 8-bit channel.
+fn extract_channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = (mask >> shift) as u64;
+    let value = ((pixel & mask) >> shift) as u64;
+    ((value * 255 + max / 2) / max) as u8
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !data.starts_with(SIGNATURE) {
+        return Err(DecodeError::UnknownFormat);
+    }
+
+    let pixels_offset = image::read_u32_le(data, 10)? as usize;
+    let header_size = image::read_u32_le(data, FILE_HEADER_SIZE)? as usize;
+    if header_size < MIN_INFO_HEADER_SIZE {
+        return Err(DecodeError::Unsupported("OS/2 bitmap header"));
+    }
+
+    let width = image::read_u32_le(data, 18)? as i32 as i64;
+    let height = image::read_u32_le(data, 22)? as i32 as i64;
+    let bits_per_pixel = image::read_u16_le(data, 28)? as usize;
+    let compression = image::read_u32_le(data, 30)?;
+    let colors_used = image::read_u32_le(data, 46)? as usize;
+
+    // Bitmaps are stored bottom-up, unless the height is negative.
+    let top_down = height < 0;
+    let (width, height) = (width.max(0) as usize, height.unsigned_abs() as usize);
+    let pixel_count = image::pixel_count(width, height)?;
+
+    let masks = match (compression, bits_per_pixel) {
+        (BI_RGB, 16) => Some(Masks { red: 0x7C00, green: 0x03E0, blue: 0x001F, alpha: 0 }),
+        // The fourth byte of 32-bit pixels is *supposed* to be unused,
+        // and some programs fill it with garbage, so we can't treat it as alpha.
+        (BI_RGB, 32) => Some(Masks { red: 0xFF_0000, green: 0x00_FF00, blue: 0x00_00FF, alpha: 0 }),
+        (BI_RGB, 1) | (BI_RGB, 2) | (BI_RGB, 4) | (BI_RGB, 8) | (BI_RGB, 24) => None,
+        (BI_BITFIELDS, 16) | (BI_BITFIELDS, 32) | (BI_ALPHABITFIELDS, 16) | (BI_ALPHABITFIELDS, 32) => {
+            // The masks come right after `BITMAPINFOHEADER`, whether or not they're part of the header.
+            let alpha = if header_size >= ALPHA_MASK_HEADER_SIZE || compression == BI_ALPHABITFIELDS {
+                image::read_u32_le(data, 66)?
+            } else {
+                0
+            };
+            Some(Masks {
+                red: image::read_u32_le(data, 54)?,
+                green: image::read_u32_le(data, 58)?,
+                blue: image::read_u32_le(data, 62)?,
+                alpha: alpha,
+            })
+        },
+        (BI_RGB, _) | (BI_BITFIELDS, _) | (BI_ALPHABITFIELDS, _) => return Err(DecodeError::Malformed("bad bit depth")),
+        _ => return Err(DecodeError::Unsupported("compressed bitmap")),
+    };
+
+    // Bitmaps with 8 bits per pixel or fewer store indices into a color table.
+    let mut palette = Vec::new();
+    if bits_per_pixel <= 8 {
+        let max_colors = 1 << bits_per_pixel;
+        let colors = if colors_used == 0 { max_colors } else { colors_used.min(max_colors) };
+        let table = image::read_bytes(data, FILE_HEADER_SIZE + header_size, colors * 4)?;
+        // Each entry is blue, green, red, and an unused byte.
+        palette.extend(table.chunks_exact(4).map(|entry| RGBA::new(entry[2], entry[1], entry[0], 0xFF)));
+    }
+
+    let row_size = width.checked_mul(bits_per_pixel)
+        .map(|bits| num_integer::div_ceil(bits, 32) * 4)
+        .ok_or(DecodeError::BadSize { width: width, height: height })?;
+    let rows = image::read_bytes(data, pixels_offset, row_size.checked_mul(height).ok_or(DecodeError::Truncated)?)?;
+
+    let mut pixels = Vec::with_capacity(pixel_count);
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        let row = &rows[row * row_size..(row + 1) * row_size];
+        for x in 0..width {
+            let pixel = match bits_per_pixel {
+                1 | 2 | 4 | 8 => {
+                    // Pixels are packed starting from the most significant bits of each byte.
+                    let bit = x * bits_per_pixel;
+                    let shift = 8 - bits_per_pixel - bit % 8;
+                    let index = (row[bit / 8] >> shift) as usize & ((1 << bits_per_pixel) - 1);
+                    *palette.get(index).ok_or(DecodeError::Malformed("color index out of range"))?
+                },
+                16 => masks.unwrap().extract(u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32),
+                24 => RGBA::new(row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 0xFF),
+                _ => {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&row[x * 4..x * 4 + 4]);
+                    masks.unwrap().extract(u32::from_le_bytes(bytes))
+                },
+            };
+            pixels.push(pixel);
+        }
+    }
+
+    Ok(Image::new(width, height, pixels))
+}
@@ -0,0 +1,302 @@
+// Decompression of DEFLATE data (RFC 1951) in a zlib wrapper (RFC 1950), which is how PNG
+// compresses its pixels. This is a straightforward decoder in the spirit of zlib's `puff`:
+// it decodes Huffman codes a bit at a time, which is slow compared to a table-driven decoder,
+// but it's small and easy to check, and we only use it for a handful of images during boot.
+
+use alloc::vec::Vec;
+use crate::checksum;
+use crate::graphics::image::DecodeError;
+
+/// Codes are never longer than 15 bits.
+const MAX_CODE_BITS: usize = 15;
+/// The literal/length alphabet has 286 symbols, plus 2 which are only used in fixed codes.
+const MAX_LITERAL_CODES: usize = 288;
+const MAX_DISTANCE_CODES: usize = 30;
+/// The lengths of the literal/length and distance codes are themselves encoded with this many codes.
+const CODE_LENGTH_CODES: usize = 19;
+const END_OF_BLOCK: u16 = 256;
+
+// The base value and the number of extra bits for each length and distance symbol.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+/// The order the code length code lengths are stored in, most common first.
+const CODE_LENGTH_ORDER: [usize; CODE_LENGTH_CODES] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+fn malformed(reason: &'static str) -> DecodeError {
+    DecodeError::Malformed(reason)
+}
+
+/// Reads bits starting from the least significant bit of each byte, as DEFLATE requires.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    count: u32,
+}
+
+impl BitReader<'_> {
+    fn new<'a>(data: &'a [u8]) -> BitReader<'a> {
+        BitReader { data: data, pos: 0, buf: 0, count: 0 }
+    }
+
+    fn bits(&mut self, n: u32) -> Result<u32, DecodeError> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or(DecodeError::Truncated)?;
+            self.pos += 1;
+            self.buf |= (byte as u32) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skip to the next byte boundary and read `len` whole bytes.
+    fn bytes(&mut self, len: usize) -> Result<&[u8], DecodeError> {
+        self.buf = 0;
+        self.count = 0;
+        let end = self.pos.checked_add(len).ok_or(DecodeError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(DecodeError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+}
+
+/// A canonical Huffman code, stored as the number of codes of each length
+/// and the symbols sorted by their codes.
+struct Huffman {
+    counts: [u16; MAX_CODE_BITS + 1],
+    symbols: [u16; MAX_LITERAL_CODES],
+}
+
+impl Huffman {
+    /// Build a code from the length of each symbol's code, where 0 means the symbol is unused.
+    fn new(lengths: &[u8]) -> Result<Huffman, DecodeError> {
+        let mut counts = [0; MAX_CODE_BITS + 1];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+
+        // Each length has twice as many codes available as the last, minus the ones used up.
+        // Codes may be left over (e.g. a distance code with only one symbol), but not overused.
+        let mut left: i32 = 1;
+        for &count in &counts[1..] {
+            left = left * 2 - count as i32;
+            if left < 0 {
+                return Err(malformed("oversubscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0; MAX_CODE_BITS + 1];
+        for len in 1..MAX_CODE_BITS {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+
+        let mut symbols = [0; MAX_LITERAL_CODES];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+
+        Ok(Huffman { counts: counts, symbols: symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, DecodeError> {
+        // Codes of each length are consecutive, and come right after
+        // the codes of the previous length (shifted left by one bit),
+        // so we can tell whether we have a complete code by comparing it to the first code of its length.
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for len in 1..=MAX_CODE_BITS {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(malformed("invalid Huffman code"))
+    }
+}
+
+/// The codes used by blocks with fixed Huffman codes.
+fn fixed_codes() -> Result<(Huffman, Huffman), DecodeError> {
+    let mut lengths = [0; MAX_LITERAL_CODES];
+    for (symbol, len) in lengths.iter_mut().enumerate() {
+        *len = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    Ok((Huffman::new(&lengths)?, Huffman::new(&[5; MAX_DISTANCE_CODES])?))
+}
+
+/// Read the codes at the start of a block with dynamic Huffman codes.
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), DecodeError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > MAX_DISTANCE_CODES {
+        return Err(malformed("too many Huffman codes"));
+    }
+
+    let mut code_length_lengths = [0; CODE_LENGTH_CODES];
+    for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_length_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_length_lengths)?;
+
+    // The lengths of both codes are stored together, and repeats may cross from one to the other.
+    let mut lengths = [0; MAX_LITERAL_CODES + MAX_DISTANCE_CODES];
+    let total = literal_count + distance_count;
+    let mut i = 0;
+    while i < total {
+        let symbol = code_length_code.decode(reader)?;
+        let (len, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                if i == 0 {
+                    return Err(malformed("repeated code length with no previous length"));
+                }
+                (lengths[i - 1], 3 + reader.bits(2)? as usize)
+            },
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > total {
+            return Err(malformed("too many code lengths"));
+        }
+        for length in &mut lengths[i..i + repeat] {
+            *length = len;
+        }
+        i += repeat;
+    }
+
+    if lengths[END_OF_BLOCK as usize] == 0 {
+        return Err(malformed("no end-of-block code"));
+    }
+    Ok((Huffman::new(&lengths[..literal_count])?, Huffman::new(&lengths[literal_count..total])?))
+}
+
+/// Decompress raw DEFLATE data. Fails if the output would be more than `limit` bytes,
+/// so that a small corrupt (or malicious) input can't make us allocate huge amounts of memory.
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, DecodeError> {
+    let mut reader = BitReader::new(data);
+    let mut out = Vec::new();
+    let too_big = || malformed("decompressed data is too large");
+
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            // Stored (uncompressed) blocks.
+            0 => {
+                let header = reader.bytes(4)?;
+                let len = u16::from_le_bytes([header[0], header[1]]);
+                let complement = u16::from_le_bytes([header[2], header[3]]);
+                if len != !complement {
+                    return Err(malformed("stored block length doesn't match its complement"));
+                }
+                if out.len() + len as usize > limit {
+                    return Err(too_big());
+                }
+                out.extend_from_slice(reader.bytes(len as usize)?);
+            },
+            kind @ 1 | kind @ 2 => {
+                let (literals, distances) = if kind == 1 { fixed_codes()? } else { dynamic_codes(&mut reader)? };
+                loop {
+                    let symbol = literals.decode(&mut reader)?;
+                    if symbol < END_OF_BLOCK {
+                        if out.len() == limit {
+                            return Err(too_big());
+                        }
+                        out.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == END_OF_BLOCK {
+                        break;
+                    }
+
+                    // Anything else is a length, followed by a distance back into the output to copy from.
+                    let symbol = (symbol - 257) as usize;
+                    if symbol >= LENGTH_BASE.len() {
+                        return Err(malformed("invalid length symbol"));
+                    }
+                    let len = LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+                    let symbol = distances.decode(&mut reader)? as usize;
+                    if symbol >= DISTANCE_BASE.len() {
+                        return Err(malformed("invalid distance symbol"));
+                    }
+                    let distance = DISTANCE_BASE[symbol] as usize + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                    if distance > out.len() {
+                        return Err(malformed("distance goes back past the start of the data"));
+                    }
+                    if out.len() + len > limit {
+                        return Err(too_big());
+                    }
+                    // The copy may overlap what it's writing (e.g. a distance of 1 repeats the last byte),
+                    // so it has to go a byte at a time.
+                    let start = out.len() - distance;
+                    for i in start..start + len {
+                        out.push(out[i]);
+                    }
+                }
+            },
+            _ => return Err(malformed("invalid block type")),
+        }
+
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompress data with a zlib header and checksum around it (see `inflate`).
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, DecodeError> {
+    if data.len() < 6 {
+        return Err(DecodeError::Truncated);
+    }
+
+    let (method, flags) = (data[0], data[1]);
+    if method & 0x0F != 8 || method >> 4 > 7 {
+        return Err(malformed("unknown compression method"));
+    }
+    if (method as u16 * 256 + flags as u16) % 31 != 0 {
+        return Err(malformed("bad zlib header check bits"));
+    }
+    if flags & 0x20 != 0 {
+        return Err(DecodeError::Unsupported("preset dictionary"));
+    }
+
+    let out = inflate(&data[2..], limit)?;
+    // The checksum comes after the compressed data, which we don't know the exact end of,
+    // but nothing comes after the checksum.
+    let mut checksum = [0; 4];
+    checksum.copy_from_slice(&data[data.len() - 4..]);
+    if u32::from_be_bytes(checksum) != checksum::adler32(&out) {
+        return Err(malformed("zlib checksum doesn't match"));
+    }
+    Ok(out)
+}
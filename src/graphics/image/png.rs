@@ -0,0 +1,304 @@
+// Portable Network Graphics (PNG). We support every standard color type and bit depth
+// and Adam7 interlacing, but ignore ancillary chunks like gamma and color profiles.
+//
+// A PNG is a signature followed by a series of chunks. The pixels are in the IDAT chunks,
+// compressed with zlib (see `inflate`), and each row is first filtered (predicted from its
+// neighbors) so that it compresses better, which we have to undo after decompressing.
+
+use alloc::vec::Vec;
+use crate::checksum::Crc32;
+use crate::graphics::color::{Color, RGBA};
+use crate::graphics::image::{self, DecodeError, Image};
+use crate::graphics::image::inflate;
+
+pub const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+const COLOR_GRAY: u8 = 0;
+const COLOR_RGB: u8 = 2;
+const COLOR_PALETTE: u8 = 3;
+const COLOR_GRAY_ALPHA: u8 = 4;
+const COLOR_RGBA: u8 = 6;
+
+/// The starting column and row and the spacing between pixels in each of the seven Adam7 passes.
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8), (4, 0, 8, 8), (0, 4, 4, 8), (2, 0, 4, 4), (0, 2, 2, 4), (1, 0, 2, 2), (0, 1, 1, 2),
+];
+
+/// Everything from the header (IHDR) chunk that we need to decode the pixels.
+struct Header {
+    width: usize,
+    height: usize,
+    bit_depth: u8,
+    color_type: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn channels(&self) -> usize {
+        match self.color_type {
+            COLOR_GRAY | COLOR_PALETTE => 1,
+            COLOR_GRAY_ALPHA => 2,
+            COLOR_RGB => 3,
+            _ => 4,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.bit_depth as usize
+    }
+
+    /// The size of a row of `width` pixels, not including the filter type byte.
+    fn row_size(&self, width: usize) -> usize {
+        num_integer::div_ceil(width * self.bits_per_pixel(), 8)
+    }
+
+    /// The size of a pass (or the whole image, if it isn't interlaced) after decompression.
+    fn pass_size(&self, (width, height): (usize, usize)) -> usize {
+        if width == 0 || height == 0 { 0 } else { (self.row_size(width) + 1) * height }
+    }
+
+    /// The size of each pass, or of the whole image if it isn't interlaced.
+    fn passes(&self) -> Vec<(usize, usize)> {
+        if !self.interlaced {
+            return alloc::vec![(self.width, self.height)];
+        }
+        ADAM7.iter().map(|&(x, y, dx, dy)| {
+            ((self.width + dx - 1 - x) / dx, (self.height + dy - 1 - y) / dy)
+        }).collect()
+    }
+}
+
+fn parse_header(data: &[u8]) -> Result<Header, DecodeError> {
+    if data.len() != 13 {
+        return Err(DecodeError::Malformed("bad header size"));
+    }
+
+    let header = Header {
+        width: image::read_u32_be(data, 0)? as usize,
+        height: image::read_u32_be(data, 4)? as usize,
+        bit_depth: data[8],
+        color_type: data[9],
+        interlaced: match data[12] {
+            0 => false,
+            1 => true,
+            _ => return Err(DecodeError::Malformed("unknown interlace method")),
+        },
+    };
+    if data[10] != 0 || data[11] != 0 {
+        return Err(DecodeError::Malformed("unknown compression or filter method"));
+    }
+
+    let depth_ok = match header.color_type {
+        COLOR_GRAY => [1, 2, 4, 8, 16].contains(&header.bit_depth),
+        COLOR_PALETTE => [1, 2, 4, 8].contains(&header.bit_depth),
+        COLOR_RGB | COLOR_GRAY_ALPHA | COLOR_RGBA => [8, 16].contains(&header.bit_depth),
+        _ => return Err(DecodeError::Malformed("unknown color type")),
+    };
+    if !depth_ok {
+        return Err(DecodeError::Malformed("bad bit depth for color type"));
+    }
+    image::pixel_count(header.width, header.height)?;
+    Ok(header)
+}
+
+/// Predict a byte from the bytes to its left, above, and above and to the left.
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = ((p - a as i16).abs(), (p - b as i16).abs(), (p - c as i16).abs());
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+/// Undo the filter on each row of a pass in place, leaving each row's filter type byte alone.
+fn unfilter(data: &mut [u8], row_size: usize, bytes_per_pixel: usize) -> Result<(), DecodeError> {
+    let stride = row_size + 1;
+    for y in 0..data.len() / stride {
+        let (above, rest) = data.split_at_mut(y * stride);
+        // The first row is filtered as if there were a row of zeroes above it.
+        let above = if y == 0 { None } else { Some(&above[above.len() - row_size..]) };
+        let filter = rest[0];
+        let row = &mut rest[1..stride];
+        for i in 0..row_size {
+            let left = if i >= bytes_per_pixel { row[i - bytes_per_pixel] } else { 0 };
+            let up = above.map_or(0, |above| above[i]);
+            let up_left = match above {
+                Some(above) if i >= bytes_per_pixel => above[i - bytes_per_pixel],
+                _ => 0,
+            };
+            row[i] = row[i].wrapping_add(match filter {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((left as u16 + up as u16) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(DecodeError::Malformed("unknown filter type")),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Read the `index`th sample of a row, scaled to 8 bits if it has fewer (or more) bits than that.
+fn sample(row: &[u8], index: usize, bit_depth: u8, scale: bool) -> u8 {
+    match bit_depth {
+        // We only keep the most significant byte of 16-bit samples.
+        16 => row[index * 2],
+        8 => row[index],
+        _ => {
+            let bits = bit_depth as usize;
+            let bit = index * bits;
+            let value = (row[bit / 8] >> (8 - bits - bit % 8)) & ((1 << bits) - 1);
+            // Palette indices must not be scaled, but gray levels must be (e.g. 1-bit white is 0xFF).
+            if scale { (value as u16 * 255 / ((1 << bits) - 1)) as u8 } else { value }
+        },
+    }
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !data.starts_with(SIGNATURE) {
+        return Err(DecodeError::UnknownFormat);
+    }
+
+    let mut header = None;
+    let mut palette = Vec::new();
+    // The color which is transparent, from the tRNS chunk, for gray and RGB images.
+    // (For palette images, tRNS has the alpha of each palette entry instead.)
+    let mut transparent = None;
+    let mut compressed = Vec::new();
+
+    let mut offset = SIGNATURE.len();
+    loop {
+        let len = image::read_u32_be(data, offset)? as usize;
+        let kind = image::read_bytes(data, offset + 4, 4)?;
+        let chunk = image::read_bytes(data, offset + 8, len)?;
+        let crc = image::read_u32_be(data, offset + 8 + len)?;
+        offset += 12 + len;
+
+        // The CRC covers the chunk type too.
+        let mut actual_crc = Crc32::new();
+        actual_crc.update(kind);
+        actual_crc.update(chunk);
+        if actual_crc.finish() != crc {
+            return Err(DecodeError::Malformed("chunk CRC doesn't match"));
+        }
+
+        match kind {
+            b"IHDR" if header.is_none() => header = Some(parse_header(chunk)?),
+            // Everything else has to come after the header.
+            _ if header.is_none() => return Err(DecodeError::Malformed("missing header")),
+            b"PLTE" => {
+                if chunk.len() % 3 != 0 || chunk.len() / 3 > 256 {
+                    return Err(DecodeError::Malformed("bad palette size"));
+                }
+                palette = chunk.chunks_exact(3).map(|c| RGBA::new(c[0], c[1], c[2], 0xFF)).collect();
+            },
+            b"tRNS" => match header.as_ref().map(|header| header.color_type) {
+                Some(COLOR_PALETTE) => {
+                    if chunk.len() > palette.len() {
+                        return Err(DecodeError::Malformed("more transparency entries than colors"));
+                    }
+                    for (color, &alpha) in palette.iter_mut().zip(chunk) {
+                        *color = RGBA::new(color.r(), color.g(), color.b(), alpha);
+                    }
+                },
+                // Samples are 16 bits here no matter the bit depth, and we compare them to unscaled samples.
+                Some(COLOR_GRAY) if chunk.len() == 2 => {
+                    let gray = u16::from_be_bytes([chunk[0], chunk[1]]);
+                    transparent = Some([gray, gray, gray]);
+                },
+                Some(COLOR_RGB) if chunk.len() == 6 => {
+                    let channel = |i: usize| u16::from_be_bytes([chunk[i], chunk[i + 1]]);
+                    transparent = Some([channel(0), channel(2), channel(4)]);
+                },
+                // Images with an alpha channel aren't supposed to have a transparency chunk,
+                // but it's harmless, so we do what other decoders do and ignore it.
+                Some(COLOR_GRAY_ALPHA) | Some(COLOR_RGBA) => {},
+                _ => return Err(DecodeError::Malformed("bad transparency chunk")),
+            },
+            b"IDAT" => compressed.extend_from_slice(chunk),
+            b"IEND" => break,
+            // Chunks whose type starts with a lowercase letter are ancillary: they can be ignored.
+            _ if kind[0] & 0x20 != 0 => {},
+            _ => return Err(DecodeError::Unsupported("unknown critical chunk")),
+        }
+    }
+
+    let header = header.ok_or(DecodeError::Malformed("missing header"))?;
+    if header.color_type == COLOR_PALETTE && palette.is_empty() {
+        return Err(DecodeError::Malformed("missing palette"));
+    }
+
+    let passes = header.passes();
+    let expected: usize = passes.iter().map(|&pass| header.pass_size(pass)).sum();
+    let mut raw = inflate::zlib_decompress(&compressed, expected)?;
+    if raw.len() != expected {
+        return Err(DecodeError::Truncated);
+    }
+
+    let bytes_per_pixel = num_integer::div_ceil(header.bits_per_pixel(), 8);
+    let channels = header.channels();
+    let mut pixels = alloc::vec![RGBA::new(0, 0, 0, 0); header.width * header.height];
+    let mut pass_start = 0;
+    for (i, &(width, height)) in passes.iter().enumerate() {
+        let size = header.pass_size((width, height));
+        let pass = &mut raw[pass_start..pass_start + size];
+        pass_start += size;
+        if size == 0 {
+            continue;
+        }
+
+        let row_size = header.row_size(width);
+        unfilter(pass, row_size, bytes_per_pixel)?;
+
+        // Where each pixel of this pass goes in the image.
+        let (start_x, start_y, step_x, step_y) = if header.interlaced { ADAM7[i] } else { (0, 0, 1, 1) };
+        for (y, row) in pass.chunks_exact(row_size + 1).enumerate() {
+            let row = &row[1..];
+            for x in 0..width {
+                let channel = |channel: usize, scale: bool| sample(row, x * channels + channel, header.bit_depth, scale);
+                let pixel = match header.color_type {
+                    COLOR_PALETTE => *palette.get(channel(0, false) as usize)
+                        .ok_or(DecodeError::Malformed("color index out of range"))?,
+                    COLOR_GRAY => {
+                        let gray = channel(0, true);
+                        RGBA::new(gray, gray, gray, 0xFF)
+                    },
+                    COLOR_GRAY_ALPHA => {
+                        let gray = channel(0, true);
+                        RGBA::new(gray, gray, gray, channel(1, true))
+                    },
+                    COLOR_RGB => RGBA::new(channel(0, true), channel(1, true), channel(2, true), 0xFF),
+                    _ => RGBA::new(channel(0, true), channel(1, true), channel(2, true), channel(3, true)),
+                };
+
+                let pixel = match transparent {
+                    Some(transparent) if is_transparent(row, x, &header, transparent) =>
+                        RGBA::new(pixel.r(), pixel.g(), pixel.b(), 0),
+                    _ => pixel,
+                };
+                pixels[(start_y + y * step_y) * header.width + start_x + x * step_x] = pixel;
+            }
+        }
+    }
+
+    Ok(Image::new(header.width, header.height, pixels))
+}
+
+/// Whether a gray or RGB pixel has exactly the color that the tRNS chunk makes transparent.
+/// This has to compare the original samples, since scaling them to 8 bits loses information.
+fn is_transparent(row: &[u8], x: usize, header: &Header, transparent: [u16; 3]) -> bool {
+    let channels = header.channels();
+    (0..channels).all(|channel| {
+        let index = x * channels + channel;
+        let value = match header.bit_depth {
+            16 => u16::from_be_bytes([row[index * 2], row[index * 2 + 1]]),
+            _ => sample(row, index, header.bit_depth, false) as u16,
+        };
+        value == transparent[channel]
+    })
+}
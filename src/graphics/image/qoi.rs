@@ -0,0 +1,98 @@
+// The Quite OK Image format (QOI), a simple lossless format which compresses about as well as PNG
+// but is far easier to decode. See https://qoiformat.org/qoi-specification.pdf.
+//
+// After a 14-byte header, the image is a series of chunks, each of which encodes
+// one or more pixels relative to the previous pixel or to a table of recently seen pixels.
+
+use alloc::vec::Vec;
+use crate::graphics::color::{Color, RGBA};
+use crate::graphics::image::{self, DecodeError, Image};
+
+pub const SIGNATURE: &[u8] = b"qoif";
+
+const HEADER_SIZE: usize = 14;
+
+// Chunks are identified by either an 8-bit tag or a 2-bit tag in the top bits of their first byte.
+// (The fourth 2-bit tag, 0xC0, is a run, which would otherwise overlap with the 8-bit tags.)
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const TAG_MASK: u8 = 0xC0;
+
+/// Where a pixel goes in the table of recently seen pixels.
+fn hash(pixel: RGBA) -> usize {
+    let (r, g, b, a) = (pixel.r() as usize, pixel.g() as usize, pixel.b() as usize, pixel.a() as usize);
+    (r * 3 + g * 5 + b * 7 + a * 11) % 64
+}
+
+pub fn decode(data: &[u8]) -> Result<Image, DecodeError> {
+    if !data.starts_with(SIGNATURE) {
+        return Err(DecodeError::UnknownFormat);
+    }
+
+    let width = image::read_u32_be(data, 4)? as usize;
+    let height = image::read_u32_be(data, 8)? as usize;
+    let channels = image::read_u8(data, 12)?;
+    if channels != 3 && channels != 4 {
+        return Err(DecodeError::Malformed("bad channel count"));
+    }
+    // The channel count and color space (byte 13) are only informative;
+    // every chunk decodes to the same kind of pixel regardless.
+    let pixel_count = image::pixel_count(width, height)?;
+
+    let mut pixels = Vec::with_capacity(pixel_count);
+    let mut seen = [RGBA::new(0, 0, 0, 0); 64];
+    let mut pixel = RGBA::new(0, 0, 0, 0xFF);
+    let mut offset = HEADER_SIZE;
+    while pixels.len() < pixel_count {
+        let op = image::read_u8(data, offset)?;
+        offset += 1;
+
+        let (r, g, b, a) = (pixel.r(), pixel.g(), pixel.b(), pixel.a());
+        let mut run = 1;
+        if op == OP_RGB {
+            let rgb = image::read_bytes(data, offset, 3)?;
+            offset += 3;
+            pixel = RGBA::new(rgb[0], rgb[1], rgb[2], a);
+        } else if op == OP_RGBA {
+            let rgba = image::read_bytes(data, offset, 4)?;
+            offset += 4;
+            pixel = RGBA::new(rgba[0], rgba[1], rgba[2], rgba[3]);
+        } else {
+            match op & TAG_MASK {
+                OP_INDEX => pixel = seen[(op & 0x3F) as usize],
+                // Each channel differs from the previous pixel by -2..=1, stored with a bias of 2.
+                OP_DIFF => {
+                    let diff = |shift: u8| ((op >> shift) & 0x03).wrapping_sub(2);
+                    pixel = RGBA::new(r.wrapping_add(diff(4)), g.wrapping_add(diff(2)), b.wrapping_add(diff(0)), a);
+                },
+                // Green differs by -32..=31, and red and blue differ from that difference by -8..=7.
+                OP_LUMA => {
+                    let next = image::read_u8(data, offset)?;
+                    offset += 1;
+                    let dg = (op & 0x3F).wrapping_sub(32);
+                    let dr = dg.wrapping_add(next >> 4).wrapping_sub(8);
+                    let db = dg.wrapping_add(next & 0x0F).wrapping_sub(8);
+                    pixel = RGBA::new(r.wrapping_add(dr), g.wrapping_add(dg), b.wrapping_add(db), a);
+                },
+                // A run of the previous pixel, repeated 1..=62 times, stored with a bias of 1.
+                _ => run = (op & 0x3F) as usize + 1,
+            }
+        }
+
+        seen[hash(pixel)] = pixel;
+        // A run may not go past the end of the image.
+        if run > pixel_count - pixels.len() {
+            return Err(DecodeError::Malformed("run goes past the end of the image"));
+        }
+        for _ in 0..run {
+            pixels.push(pixel);
+        }
+    }
+
+    // The chunks are followed by an end marker, which we don't bother to check;
+    // we already have all of the pixels.
+    Ok(Image::new(width, height, pixels))
+}
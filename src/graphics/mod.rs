@@ -1,2 +1,4 @@
 pub mod color;
 pub mod font;
+pub mod image;
+pub mod rect;
@@ -1,8 +1,9 @@
-use alloc::format;
 use core::cell::UnsafeCell;
-use crate::driver::tty::Tty;
+use core::fmt::{self, Write};
+use crate::driver::tty::{Tty, TtyWriter};
 use crate::driver::tty::serial::SerialTty;
-use log::{Record, LevelFilter, Metadata, SetLoggerError};
+use crate::driver::tty::text_display::TextDisplayTty;
+use log::{Level, Record, LevelFilter, Metadata, SetLoggerError};
 
 enum GlobalLogger {
     None,
@@ -13,6 +14,83 @@ enum GlobalLogger {
 
 use GlobalLogger::*;
 
+/// The escape sequence used to highlight each log level,
+/// which works the same on serial terminals and on `TextDisplayTty`.
+fn level_style(level: Level) -> &'static str {
+    match level {
+        Level::Error => "\u{1B}[1;31m",
+        Level::Warn => "\u{1B}[1;33m",
+        Level::Info => "\u{1B}[32m",
+        Level::Debug => "\u{1B}[36m",
+        Level::Trace => "\u{1B}[2m",
+    }
+}
+
+/// How many of the most recent log messages we keep (see `RecentLog`).
+pub const RECENT_LOG_LINES: usize = 16;
+/// Messages longer than this many bytes are cut off in the recent log.
+const RECENT_LOG_LINE_LENGTH: usize = 160;
+
+/// The last few log messages, kept in a fixed-size ring buffer so that the panic screen
+/// can show what happened leading up to a panic, even if the allocator is broken.
+#[derive(Copy, Clone)]
+pub struct RecentLog {
+    lines: [[u8; RECENT_LOG_LINE_LENGTH]; RECENT_LOG_LINES],
+    lengths: [usize; RECENT_LOG_LINES],
+    /// Where the next message goes, which is also the oldest message once the buffer is full.
+    next: usize,
+    count: usize,
+}
+
+impl RecentLog {
+    const fn new() -> RecentLog {
+        RecentLog {
+            lines: [[0; RECENT_LOG_LINE_LENGTH]; RECENT_LOG_LINES],
+            lengths: [0; RECENT_LOG_LINES],
+            next: 0,
+            count: 0,
+        }
+    }
+
+    fn push(&mut self, level: Level, args: &fmt::Arguments) {
+        let mut line = LineWriter { line: &mut self.lines[self.next], len: 0 };
+        let _ = write!(line, "{} - {}", level, args);
+        self.lengths[self.next] = line.len;
+        self.next = (self.next + 1) % RECENT_LOG_LINES;
+        self.count = (self.count + 1).min(RECENT_LOG_LINES);
+    }
+
+    /// The messages, from oldest to newest.
+    pub fn lines(&self) -> impl Iterator<Item = &str> {
+        let start = (self.next + RECENT_LOG_LINES - self.count) % RECENT_LOG_LINES;
+        (0..self.count).map(move |i| {
+            let i = (start + i) % RECENT_LOG_LINES;
+            // `LineWriter` only cuts lines off at character boundaries.
+            core::str::from_utf8(&self.lines[i][..self.lengths[i]]).unwrap_or("")
+        })
+    }
+}
+
+/// Writes into a line of the recent log, silently cutting it off when it's full.
+struct LineWriter<'a> {
+    line: &'a mut [u8; RECENT_LOG_LINE_LENGTH],
+    len: usize,
+}
+
+impl Write for LineWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            let len = c.len_utf8();
+            if self.len + len > self.line.len() {
+                break;
+            }
+            c.encode_utf8(&mut self.line[self.len..]);
+            self.len += len;
+        }
+        Ok(())
+    }
+}
+
 impl log::Log for GlobalLogger {
     fn enabled(&self, _metadata: &Metadata) -> bool {
         match self {
@@ -25,9 +103,17 @@ impl log::Log for GlobalLogger {
         match self {
             None => {},
             Tty(tty) => unsafe {
-                // TODO: Lose the dependency on the `format!` macro
-                // so we don't have to allocate a String here.
-                (*tty.get()).puts(&format!("{} - {}", record.level(), record.args()));
+                // Messages are formatted straight to each TTY rather than into a `String`,
+                // so that we can still log when the allocator isn't working.
+                let style = level_style(record.level());
+                let _ = write!(TtyWriter(&mut *tty.get()), "{}{}\u{1B}[0m - {}\n", style, record.level(), record.args());
+                RECENT_LOG.push(record.level(), record.args());
+                if let Some(console) = &mut CONSOLE {
+                    let _ = write!(TtyWriter(&mut **console), "{}{}\u{1B}[0m - {}\n", style, record.level(), record.args());
+                    // Nothing is displayed on the console until it's flushed,
+                    // and there's no point in logging things nobody can see.
+                    console.flush();
+                }
             },
         }
     }
@@ -37,6 +123,9 @@ impl log::Log for GlobalLogger {
             None => {},
             Tty(tty) => unsafe {
                 (*tty.get()).flush();
+                if let Some(console) = &mut CONSOLE {
+                    console.flush();
+                }
             },
         }
     }
@@ -48,6 +137,9 @@ unsafe impl Sync for GlobalLogger {}
 unsafe impl Send for GlobalLogger {}
 
 static mut LOGGER: GlobalLogger = GlobalLogger::None;
+/// A graphical console which log messages are copied to, if there is one.
+static mut CONSOLE: Option<&'static mut TextDisplayTty<'static>> = Option::None;
+static mut RECENT_LOG: RecentLog = RecentLog::new();
 
 pub fn init() -> Result<(), SetLoggerError> {
     unsafe {
@@ -61,3 +153,31 @@ pub fn set_tty(tty: SerialTty) {
         LOGGER = GlobalLogger::Tty(UnsafeCell::new(tty));
     }
 }
+
+/// Copy all future log messages to a console, in addition to the serial port.
+pub fn set_console(console: &'static mut TextDisplayTty<'static>) {
+    unsafe {
+        CONSOLE = Some(console);
+    }
+}
+
+/// Run `f` on the console, if there is one.
+///
+/// The logger draws to the console too, so `f` must not log anything.
+pub fn with_console<R>(f: impl FnOnce(&TextDisplayTty<'static>) -> R) -> Option<R> {
+    unsafe { CONSOLE.as_ref().map(|console| f(console)) }
+}
+
+/// Stop copying log messages to the console.
+/// We can't trust the console after a panic (the panic may have come from the console itself),
+/// so the panic handler detaches it before logging anything.
+pub fn detach_console() {
+    unsafe {
+        CONSOLE = Option::None;
+    }
+}
+
+/// A copy of the most recent log messages.
+pub fn recent_log() -> RecentLog {
+    unsafe { RECENT_LOG }
+}
@@ -15,16 +15,27 @@
 // Used to conveniently define x86 interrupt handling routines.
 #![feature(abi_x86_interrupt)]
 #![feature(generic_associated_types)]
+// Used to show panic messages without the rest of `PanicInfo`'s formatting.
+#![feature(panic_info_message)]
 extern crate alloc;
 
 mod arch;
+mod boot_options;
+mod checksum;
 mod driver;
 mod graphics;
 mod memory;
 mod logger;
+mod panic_screen;
+mod screenshot;
+mod unicode;
 
 use alloc::vec::Vec;
+use crate::boot_options::BootOptions;
+use crate::driver::graphic_display::framebuffer::FramebufferInfo;
+use crate::memory::paging::memory_attributes::MemoryAttributesTable;
 use uefi::prelude::*;
+use uefi::table::boot::MemoryDescriptor;
 
 // # Why did you choose to make bootproof a UEFI application?
 //
@@ -155,8 +166,25 @@ fn efi_main(handle: Handle, st_boot: SystemTable<Boot>) -> Status {
     // We can't let it be de-allocated because it is allocated using the UEFI allocator,
     // for the reasons described above.
     let mut mmap_buf = Vec::new();
-    let (_mmap, st) = {
+    // We also keep our own copy of the final memory map, because the one UEFI gives us
+    // can only be iterated through once, and both the allocator and the page tables need it.
+    // It has to be allocated up front too, since we have no allocator at all while we fill it.
+    let mut memory_map = Vec::new();
+    let (st, framebuffer, attributes, options) = {
         let bs = st_boot.boot_services();
+
+        // The load options are only available through a boot service too.
+        let options = BootOptions::from_loaded_image(handle, bs);
+
+        // The Graphics Output Protocol is a boot service, so we have to ask it
+        // where the framebuffer is now, while we still can.
+        // The framebuffer itself sticks around after we exit boot services.
+        let framebuffer = FramebufferInfo::from_gop(bs);
+
+        // Likewise, the memory attributes table outlives boot services,
+        // but the way to find it is through a table that's easier to read now.
+        let attributes = MemoryAttributesTable::find(&st_boot);
+
         // A lot of allocations can happen between the buffer being allocated
         // and the buffer being populated when the boot services exit
         // (both by us and the UEFI's own processes;
@@ -165,6 +193,8 @@ fn efi_main(handle: Handle, st_boot: SystemTable<Boot>) -> Status {
         // 1024 is a number that I came up with by repeatedly testing numbers
         // until the kernel stopped crashing.
         mmap_buf.resize(bs.memory_map_size() + 1024, 0);
+        // Descriptors are at least this big, so this is at least as many as fit in the buffer.
+        memory_map.reserve_exact(mmap_buf.len() / core::mem::size_of::<MemoryDescriptor>());
 
         // First we read the memory map so that the runtime allocator
         // can decide how much space it needs to allocate for its own data structures
@@ -172,32 +202,38 @@ fn efi_main(handle: Handle, st_boot: SystemTable<Boot>) -> Status {
         // Between now and exiting boot services, only kernel and boot services will be made,
         // not changes to reserved memory and so forth (or at least I hope not!
         // so the amount of physical memory the allocator needs to keep track of will not change.
-        use crate::memory::allocator::standard::StandardAllocator;
+        use crate::memory::frame::FrameAllocator;
         let mut allocator;
         {
             let mut mmap = bs.memory_map(mmap_buf.as_mut_slice())
                 .expect_success("Failed to exit the UEFI boot services.").1;
-            allocator = StandardAllocator::new(&mut mmap);
+            allocator = FrameAllocator::new(&mut mmap);
         }
 
         // Actually exit UEFI boot services!
-        let (st, mut mmap) = st_boot.exit_boot_services(handle, mmap_buf.as_mut_slice())
+        let (st, mmap) = st_boot.exit_boot_services(handle, mmap_buf.as_mut_slice())
             .expect_success("Failed to exit the UEFI boot services.");
+        // As promised. We won't have a heap again until we've set up paging,
+        // so until then, any allocation is a bug.
+        unsafe { ALLOCATOR = GlobalAllocator::None; }
+        // This can't reallocate, because the memory map buffer couldn't have held any more entries.
+        memory_map.extend(mmap.copied());
 
         // We now populate the allocator with the final memory map.
         // Before we were just allocating space for data structures,
         // but the actual memory used wasn't set in stone; now it is.
-        // Since we don't distinguish boot services memory
-        // from unallocated memory after exiting boot services,
-        // perhaps we could just populate it from the original memory map and ignore this entirely?
-        // I'm already making the assumption that reserved/runtime memory won't change,
-        // and I don't make any new kernel allocations between then and now.
-        allocator.populate(&mut mmap);
-        unsafe { ALLOCATOR = GlobalAllocator::Standard(allocator); }
-
-        (mmap, st)
+        // Boot services memory is left alone for now: we're still running on
+        // UEFI's page tables, GDT, IDT, and stack, which are all in it.
+        allocator.populate(&mut memory_map.iter());
+        unsafe { memory::frame::init(allocator); }
+
+        (st, framebuffer, attributes, options)
     };
 
+    if framebuffer.is_none() {
+        log::warn!("No usable framebuffer was found; continuing without graphics.");
+    }
+
     // Now that UEFI is no longer handling interrupts,
     // we want them disabled until we set up our own handler,
     // which we will do... also right now.
@@ -205,6 +241,10 @@ fn efi_main(handle: Handle, st_boot: SystemTable<Boot>) -> Status {
     use x86_64::instructions::interrupts;
     interrupts::disable();
 
+    // Switch to our own page tables, so that the kernel's code and read-only data are protected
+    // and null pointer dereferences fault.
+    unsafe { memory::paging::init(&memory_map, attributes, framebuffer); }
+
     use crate::arch::x86_64::{gdt, idt};
     // TODO: Resetting the GDT hasn't actually proven to be necessary in the emulator.
     //   However, I'm not sure if that's true in general,
@@ -212,22 +252,82 @@ fn efi_main(handle: Handle, st_boot: SystemTable<Boot>) -> Status {
     //   That said, further research is needed.
     gdt::load();
     idt::load();
+
+    // Last of all, we move off the stack UEFI gave us, and onto the kernel's own, which has a guard page.
+    // The stack we're on now is about to be freed, so what's left of setting up continues over there.
+    let boot = Boot {
+        st: st,
+        framebuffer: framebuffer,
+        options: options,
+        memory_map: memory_map,
+    };
+    unsafe { arch::x86_64::stack::switch(finish_boot, &boot as *const Boot as usize) }
+}
+
+/// Everything `efi_main` has to hand over to `finish_boot` when it switches stacks.
+struct Boot {
+    st: SystemTable<uefi::table::Runtime>,
+    framebuffer: Option<FramebufferInfo>,
+    options: BootOptions,
+    memory_map: Vec<MemoryDescriptor>,
+}
+
+/// The rest of `efi_main`, on the kernel stack. `boot` points to a `Boot` on the old stack.
+extern "sysv64" fn finish_boot(boot: usize) -> ! {
+    // The old stack is never returned to, so this is the only copy (and nothing will drop the original).
+    let Boot { st, framebuffer, options, memory_map } = unsafe { core::ptr::read(boot as *const Boot) };
+
+    // Now that we've replaced everything UEFI left running (its stack included),
+    // we don't need any of boot services memory anymore.
+    unsafe {
+        use crate::memory::frame::frame_allocator;
+        frame_allocator().reclaim(&memory_map);
+    }
+
+    // Finally, we can have a heap again. It gets to use as much memory as we have left.
+    unsafe {
+        use crate::memory::allocator::{ALLOCATOR, GlobalAllocator};
+        use crate::memory::allocator::slab::SlabAllocator;
+        use crate::memory::allocator::standard::StandardAllocator;
+        ALLOCATOR = GlobalAllocator::Standard(SlabAllocator::new(StandardAllocator::new()));
+    }
+
     // We now have our own interrupt handler so we can re-enable them now.
     // That said, we still need to set up APIC to recieve interrupts for devices,
     // which isn't something that I've programmed yet. I'm working on it, though!
-    interrupts::enable();
+    x86_64::instructions::interrupts::enable();
 
     // Everything up to this point has been setting up the CPU state, drivers, etc.
     // Now we begin running actual programs
     // (or in this case, since we don't support actual programs yet,
     // whatever debug stuff I want to run).
-    main(st)
+    main(st, framebuffer, options)
 }
 
-fn main(st: SystemTable<uefi::table::Runtime>) -> ! {
+fn main(st: SystemTable<uefi::table::Runtime>, framebuffer: Option<FramebufferInfo>, options: BootOptions) -> ! {
+    if let Some(info) = framebuffer {
+        init_console(info, options);
+    }
+
     // Put whatever code you want for debugging/testing purposes here...
     arch::x86_64::breakpoint();
 
+    if options.screenshot {
+        use crate::driver::tty::serial::{COM1_PORT, SerialTty};
+        // The logger has its own handle to the serial port,
+        // but it won't write anything while we're sending the screenshot.
+        let sent = logger::with_console(|console| match console.display().graphic_display() {
+            Some(display) => {
+                screenshot::send(display, &mut SerialTty::new(COM1_PORT));
+                true
+            },
+            None => false,
+        });
+        if sent != Some(true) {
+            log::warn!("Can't take a screenshot without a display.");
+        }
+    }
+
     // There's nothing left for us to do at this point,
     // because there are no meaningful programs to run.
     // Instead, we'll just spin forever until the computer is turned off.
@@ -236,16 +336,77 @@ fn main(st: SystemTable<uefi::table::Runtime>) -> ! {
     loop { x86_64::instructions::hlt(); }
 }
 
+/// Set up a terminal on the framebuffer and copy the kernel log to it.
+fn init_console(info: FramebufferInfo, options: BootOptions) {
+    use alloc::boxed::Box;
+    use crate::driver::graphic_display::buffered::BufferedDisplay;
+    use crate::driver::graphic_display::framebuffer::Framebuffer;
+    use crate::driver::text_display::graphic::GraphicTextDisplay;
+    use crate::driver::tty::Tty;
+    use crate::driver::tty::text_display::TextDisplayTty;
+    use crate::graphics::color::{COLOR_BLACK, COLOR_WHITE};
+    use crate::graphics::font::Font;
+    use crate::graphics::font::bdf::{self, BdfFont};
+    use crate::graphics::font::fallback::FallbackFont;
+    use crate::graphics::font::psf;
+
+    // The font is embedded in the kernel, so this can only fail if the kernel was built wrong,
+    // but that's no reason to give up on logging to the serial port.
+    let font = match psf::parse(psf::COZETTE) {
+        Ok(font) => font,
+        Err(err) => {
+            log::error!("Failed to load the console font: {}", err);
+            return;
+        },
+    };
+
+    // The console is used for as long as the kernel is running,
+    // so we leak everything it depends on to give it a static lifetime.
+    let font: &'static _ = Box::leak(Box::new(font));
+    // Cozette is missing a lot of symbols, which we fill in from a font with better coverage.
+    // The fallback font only makes the console nicer to look at, so we can do without it too.
+    let font: &'static dyn Font = match BdfFont::parse(bdf::DEJAVU_SANS_MONO) {
+        Ok(fallback) => {
+            let fallback: &'static _ = Box::leak(Box::new(fallback));
+            Box::leak(Box::new(FallbackFont::new(&[font as &dyn Font, fallback])))
+        },
+        Err(err) => {
+            log::error!("Failed to load the fallback font: {}", err);
+            font
+        },
+    };
+    // Nothing else has touched the framebuffer since we exited boot services.
+    let display = Box::leak(Box::new(BufferedDisplay::new(unsafe { Framebuffer::new(info) })));
+    let text_display = Box::leak(Box::new(match options.console_scale {
+        Some(scale) => GraphicTextDisplay::with_scale(display, font, scale, COLOR_BLACK, COLOR_WHITE),
+        None => GraphicTextDisplay::new(display, font, COLOR_BLACK, COLOR_WHITE),
+    }));
+    // The panic screen uses the same font and scale as the console,
+    // but it gets its own copy of the framebuffer so that it doesn't depend on the console at all.
+    unsafe {
+        panic_screen::init(info, font, text_display.scale());
+    }
+    let tty = Box::leak(Box::new(TextDisplayTty::new(text_display)));
+    tty.flush();
+    logger::set_console(tty);
+}
+
 #[macro_export]
 macro_rules! panic {
-    ($($arg:expr),*) => {{
-        log::error!($($arg),*);
-        // FIXME: Panic shouldn't depend on an architecture-specific function.
-        crate::arch::x86_64::halt()
-    }}
+    ($($arg:tt)*) => {
+        crate::panic_screen::panic(
+            format_args!($($arg)*),
+            Some(crate::panic_screen::Location::new(file!(), line!(), column!())),
+        )
+    }
 }
 
 #[panic_handler]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    panic!("{}", info);
+    let location = info.location()
+        .map(|location| panic_screen::Location::new(location.file(), location.line(), location.column()));
+    match info.message() {
+        Some(message) => panic_screen::panic(*message, location),
+        None => panic_screen::panic(format_args!("(no message)"), location),
+    }
 }
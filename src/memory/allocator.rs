@@ -1,3 +1,4 @@
+pub mod slab;
 pub mod standard;
 pub mod uefi;
 
@@ -6,7 +7,7 @@ use core::alloc::Layout;
 
 pub enum GlobalAllocator {
     None,
-    Standard(standard::StandardAllocator),
+    Standard(slab::SlabAllocator),
     Uefi(uefi::UefiAllocator),
 }
 
@@ -0,0 +1,198 @@
+use alloc::alloc::GlobalAlloc;
+use core::alloc::Layout;
+use core::cell::UnsafeCell;
+use core::mem;
+use core::ptr;
+use super::standard::StandardAllocator;
+
+// Most allocations are tiny (a `String` here, a `Box` there),
+// and rounding every one of them up to a whole page wastes nearly all of the page.
+// Instead, small allocations are rounded up to the next power of two (a "size class"),
+// and each size class carves whole pages (slabs) into objects of that size.
+// Since slabs are aligned to their size and objects are a power of two in size,
+// every object is aligned to its own size, so an allocation which needs more alignment
+// than it has size just goes into a bigger size class.
+//
+// Each slab starts with a header that keeps track of its own free objects,
+// and since slabs are aligned, we can find the header of any object by rounding its address down.
+// Slabs with free objects are kept on a list for their size class, and once all of a slab's objects
+// are free, the slab goes back to the page allocator (unless it's the only empty one of its class,
+// in which case we hang on to it, so that allocating and freeing one object doesn't map and unmap a page).
+
+const PAGE_SIZE: usize = 4096;
+/// Objects have to be big enough to hold a pointer to the next free object.
+const MIN_SIZE_ORDER: usize = 3;
+/// Anything bigger than half a page is allocated directly from the page allocator,
+/// because it wouldn't save any space anyway.
+const MAX_SIZE_ORDER: usize = 11;
+const SIZE_CLASSES: usize = MAX_SIZE_ORDER - MIN_SIZE_ORDER + 1;
+/// Slabs hold at least this many objects (counting the space taken by the header),
+/// so that the header doesn't take up half of a slab of big objects.
+const MIN_OBJECTS: usize = 8;
+
+/// A free object, which holds a pointer to the next one in its slab's free list.
+struct FreeObject {
+    next: *mut FreeObject,
+}
+
+/// The start of every slab.
+struct Slab {
+    free: *mut FreeObject,
+    /// The number of objects which are allocated.
+    used: usize,
+    /// The neighbours of the slab in its size class's list of slabs with free objects.
+    prev: *mut Slab,
+    next: *mut Slab,
+}
+
+/// The slabs of one size class.
+#[derive(Clone, Copy)]
+struct SizeClass {
+    /// The slabs which have free objects, but aren't empty.
+    partial: *mut Slab,
+    /// An empty slab that we're holding on to, if any.
+    empty: *mut Slab,
+}
+
+/// The kernel heap, for objects of any size.
+pub struct SlabAllocator {
+    pages: StandardAllocator,
+    classes: UnsafeCell<[SizeClass; SIZE_CLASSES]>,
+}
+
+/// The size class of an allocation, or `None` if it's too big for any of them.
+fn size_class(layout: Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align()).next_power_of_two();
+    let order = (size.trailing_zeros() as usize).max(MIN_SIZE_ORDER);
+    if order > MAX_SIZE_ORDER {
+        return None;
+    }
+    Some(order - MIN_SIZE_ORDER)
+}
+
+fn object_size(class: usize) -> usize {
+    1 << (class + MIN_SIZE_ORDER)
+}
+
+fn slab_layout(class: usize) -> Layout {
+    let size = (object_size(class) * MIN_OBJECTS).max(PAGE_SIZE);
+    Layout::from_size_align(size, size).unwrap()
+}
+
+impl SizeClass {
+    unsafe fn push(&mut self, slab: *mut Slab) {
+        (*slab).prev = ptr::null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn remove(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.partial = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+    }
+}
+
+impl SlabAllocator {
+    pub fn new(pages: StandardAllocator) -> SlabAllocator {
+        let class = SizeClass { partial: ptr::null_mut(), empty: ptr::null_mut() };
+        SlabAllocator {
+            pages: pages,
+            classes: UnsafeCell::new([class; SIZE_CLASSES]),
+        }
+    }
+
+    /// Get a new slab from the page allocator, with all of its objects free.
+    unsafe fn new_slab(&self, class: usize) -> *mut Slab {
+        let layout = slab_layout(class);
+        let slab = self.pages.alloc(layout) as *mut Slab;
+        if slab.is_null() {
+            return slab;
+        }
+        let size = object_size(class);
+        // The header takes up however many objects it needs at the start of the slab.
+        let first = num_integer::div_ceil(mem::size_of::<Slab>(), size) * size;
+        let mut free = ptr::null_mut();
+        // Objects are pushed from the end so that they're handed out in address order.
+        for offset in (first..layout.size()).step_by(size).rev() {
+            let object = (slab as *mut u8).add(offset) as *mut FreeObject;
+            (*object).next = free;
+            free = object;
+        }
+        ptr::write(slab, Slab {
+            free: free,
+            used: 0,
+            prev: ptr::null_mut(),
+            next: ptr::null_mut(),
+        });
+        slab
+    }
+}
+
+unsafe impl GlobalAlloc for SlabAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let class = match size_class(layout) {
+            Some(class) => class,
+            None => return self.pages.alloc(layout),
+        };
+        let classes = &mut *self.classes.get();
+        let slab = if !classes[class].partial.is_null() {
+            classes[class].partial
+        } else {
+            let slab = if !classes[class].empty.is_null() {
+                mem::replace(&mut classes[class].empty, ptr::null_mut())
+            } else {
+                self.new_slab(class)
+            };
+            if slab.is_null() {
+                return ptr::null_mut();
+            }
+            classes[class].push(slab);
+            slab
+        };
+
+        let object = (*slab).free;
+        (*slab).free = (*object).next;
+        (*slab).used += 1;
+        if (*slab).free.is_null() {
+            classes[class].remove(slab);
+        }
+        object as *mut u8
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let class = match size_class(layout) {
+            Some(class) => class,
+            None => return self.pages.dealloc(ptr, layout),
+        };
+        let classes = &mut *self.classes.get();
+        let slab_layout = slab_layout(class);
+        let slab = (ptr as usize & !(slab_layout.size() - 1)) as *mut Slab;
+
+        // A slab with no free objects isn't on the list, so it has to go back on.
+        if (*slab).free.is_null() {
+            classes[class].push(slab);
+        }
+        let object = ptr as *mut FreeObject;
+        (*object).next = (*slab).free;
+        (*slab).free = object;
+        (*slab).used -= 1;
+
+        if (*slab).used == 0 {
+            classes[class].remove(slab);
+            if classes[class].empty.is_null() {
+                classes[class].empty = slab;
+            } else {
+                self.pages.dealloc(slab as *mut u8, slab_layout);
+            }
+        }
+    }
+}
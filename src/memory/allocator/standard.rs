@@ -1,142 +1,226 @@
 use alloc::alloc::GlobalAlloc;
-use alloc::vec::Vec;
+use buddy::{Buddy, Link, Links, MAX_ORDER};
 use core::alloc::Layout;
 use core::cell::UnsafeCell;
-use uefi::table::boot::MemoryDescriptor;
+use core::mem;
+use core::ptr;
+use core::slice;
+use crate::memory::frame::{Zone, frame_allocator};
+use crate::memory::paging::{PagingError, kernel_page_tables};
+use x86_64::VirtAddr;
+use x86_64::structures::paging::{Page, PageTableFlags};
 
-// TODO: Support granularity better than pages.
-// TODO: Use an allocation algorithm that isn't absolute garbage!!
-
-/// **This allocator only supports page-level granularity.**
-/// Be careful not to use it for small allocations.
-pub struct StandardAllocator {
-    pages: UnsafeCell<Vec<u8>>,
-}
+/// Where the heap lives in virtual memory: 64 TiB, far above the identity mapping of physical memory.
+pub const HEAP_START: u64 = 0x0000_4000_0000_0000;
 
 const PAGE_SIZE: usize = 4096;
 
-pub fn get_bit(bytes: &[u8], index: usize) -> bool {
-    let (byte, bit) = num_integer::div_rem(index, 8);
-    let mask = 0b10000000u8 >> bit;
-    bytes[byte] & mask > 0
+/// The heap is split into chunks of the largest block a buddy allocator can hand out (1 GiB),
+/// each with a buddy allocator of its own.
+const CHUNK_PAGES: usize = 1 << MAX_ORDER;
+/// The heap has room for 64 GiB, however much memory there actually is.
+/// It's only virtual memory, and a chunk isn't set up until the heap grows into it.
+const CHUNKS: usize = 64;
+
+/// Where each chunk keeps track of itself, right after the heap.
+const METADATA_START: u64 = HEAP_START + (CHUNKS * CHUNK_PAGES * PAGE_SIZE) as u64;
+/// How much room each chunk has for keeping track of itself. Most of it is never mapped.
+const METADATA_SIZE: usize = 8 << 20;
+/// Where a chunk's table of free list entries starts, relative to the rest of its metadata.
+const ENTRIES_OFFSET: usize = 4 << 20;
+
+const ENTRIES_PER_PAGE: usize = PAGE_SIZE / mem::size_of::<Link>();
+const ENTRY_PAGES: usize = CHUNK_PAGES / ENTRIES_PER_PAGE;
+/// An allocation may need free list entries on up to two pages of the table that aren't mapped yet,
+/// and mapping each of them could take three new page tables as well.
+const ENTRY_FRAMES: usize = 2 * 4;
+
+/// The free list entries of a chunk.
+///
+/// Free heap pages aren't mapped to anything, so the entries can't be kept in the pages themselves.
+/// Instead they're kept in a table on the side, which is mapped a page at a time as it's needed.
+///
+/// Blocks which start on a multiple of `ENTRIES_PER_PAGE` pages could be anywhere
+/// (e.g. the halves split off a fresh chunk), so their entries have a small table of their own,
+/// which is always mapped. Any other free block is what's left of a bigger block
+/// after part of it was allocated, so only the first and last pages of an allocation
+/// can need a page of the table that isn't mapped yet, and a deallocation never does.
+struct ChunkLinks {
+    aligned: *mut Link,
+    entries: *mut Link,
+    /// Which pages of `entries` are mapped.
+    mapped: [u64; ENTRY_PAGES / 64],
 }
 
-pub fn set_bit(bytes: &mut [u8], index: usize, value: bool) {
-    let (byte, bit) = num_integer::div_rem(index, 8);
-    let mask = 0b10000000u8 >> bit;
-    if value {
-        bytes[byte] |= mask;
-    } else {
-        bytes[byte] &= !mask;
+impl ChunkLinks {
+    fn entry(&self, index: usize) -> *mut Link {
+        if index % ENTRIES_PER_PAGE == 0 {
+            unsafe { self.aligned.add(index / ENTRIES_PER_PAGE) }
+        } else {
+            unsafe { self.entries.add(index) }
+        }
     }
 }
 
-impl StandardAllocator {
-    /// Allocates a new allocator data structure sufficient
-    /// to use the physical memory provided in the memory map.
-    /// With what allocator does the allocator get allocated? The UEFI allocator.
-    /// This does *not* pre-populate the allocator with usage data;
-    /// by default, it will behave as though every page were allocated.
-    /// Use `populate` to fill the allocator with actual data
-    /// using the map that UEFI provides when you exit boot services.
-    pub fn new<'buf>(mmap: &mut impl ExactSizeIterator<Item = &'buf MemoryDescriptor>) -> StandardAllocator {
-        // Try to find the largest physical address
-        // and create a bitmap allowing the allocation of that much memory.
-        let greatest_physical_page =
-            mmap.map(|d| num_integer::div_ceil(d.phys_start as usize, PAGE_SIZE) + d.page_count as usize).max()
-                .unwrap();
-
-        let mut pages = Vec::with_capacity(num_integer::div_ceil(greatest_physical_page, 8));
-        // I can fit up to 8 pages in a byte in my bitmap.
-        pages.resize(num_integer::div_ceil(greatest_physical_page, 8), 0xFF);
+impl Links for ChunkLinks {
+    fn get(&self, index: usize) -> Link {
+        // Anything we're asked to read has been written, so it's mapped already.
+        unsafe { ptr::read(self.entry(index)) }
+    }
 
-        StandardAllocator {
-            pages: UnsafeCell::new(pages)
+    fn set(&mut self, index: usize, link: Link) {
+        let page = index / ENTRIES_PER_PAGE;
+        if index % ENTRIES_PER_PAGE != 0 && self.mapped[page / 64] & 1 << (page % 64) == 0 {
+            // `StandardAllocator::alloc` makes sure there are enough frames for this before it allocates.
+            unsafe {
+                map_fresh(self.entry(index) as u64, PAGE_SIZE).expect("Failed to map the heap's free lists!");
+            }
+            self.mapped[page / 64] |= 1 << (page % 64);
         }
+        unsafe { ptr::write(self.entry(index), link) }
     }
+}
 
-    pub fn populate<'buf>(&mut self, mmap: &mut impl ExactSizeIterator<Item = &'buf MemoryDescriptor>) {
-        let self_pages = unsafe { &mut *self.pages.get() };
-        // Mark all unsable memory as free for allocations.
-        for entry in mmap {
-            use uefi::table::boot::MemoryType;
-            if entry.ty == MemoryType::BOOT_SERVICES_CODE
-                || entry.ty == MemoryType::BOOT_SERVICES_DATA
-                || entry.ty == MemoryType::CONVENTIONAL {
-                let base = entry.phys_start as usize / PAGE_SIZE;
-                for offset in 0..entry.page_count as usize {
-                    set_bit(self_pages.as_mut_slice(), base + offset, false);
-                }
+type Chunk = Buddy<'static, ChunkLinks>;
+
+/// Map fresh frames to the `len` bytes at `addr`,
+/// or if we run out of memory, give back whatever we mapped and map nothing at all.
+unsafe fn map_fresh(addr: u64, len: usize) -> Result<(), PagingError> {
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+    let start = Page::containing_address(VirtAddr::new(addr));
+    let end = Page::containing_address(VirtAddr::new(addr + len as u64 - 1)) + 1;
+    for page in Page::range(start, end) {
+        let result = match frame_allocator().allocate(Zone::Normal) {
+            Some(frame) => kernel_page_tables().map(page, frame, flags).map_err(|err| {
+                frame_allocator().deallocate(frame);
+                err
+            }),
+            None => Err(PagingError::OutOfMemory),
+        };
+        if let Err(err) = result {
+            for page in Page::range(start, page) {
+                let frame = kernel_page_tables().unmap(page).expect("Heap page wasn't mapped!");
+                frame_allocator().deallocate(frame);
             }
+            return Err(err);
         }
+    }
+    Ok(())
+}
 
-        // Even if the zero address is valid memory, we *definitely* don't want to allocate it.
-        set_bit(self_pages.as_mut_slice(), 0, true);
+/// The kernel heap.
+///
+/// Heap memory is virtual memory: each page is mapped to whatever frame
+/// the frame allocator gives us when it's allocated, and unmapped and freed along with it.
+/// That means a large allocation doesn't need physically contiguous memory,
+/// just a run of heap addresses, which we have plenty of.
+/// The runs of heap addresses come from buddy allocators,
+/// so allocations are aligned to the next power of two pages, and can be up to 1 GiB.
+///
+/// **This allocator only supports page-level granularity.**
+/// Small allocations should go through the `SlabAllocator` in front of it instead.
+pub struct StandardAllocator {
+    /// The chunks that have been set up so far, which are always the first few.
+    chunks: UnsafeCell<[*mut Chunk; CHUNKS]>,
+}
+
+impl StandardAllocator {
+    /// Create a heap with nothing in it. It must not be used until paging is set up.
+    pub fn new() -> StandardAllocator {
+        StandardAllocator {
+            chunks: UnsafeCell::new([ptr::null_mut(); CHUNKS]),
+        }
+    }
+
+    fn page(index: usize) -> Page {
+        Page::containing_address(VirtAddr::new(HEAP_START + (index * PAGE_SIZE) as u64))
+    }
+
+    /// Set up chunk number `number` with all of its pages free.
+    ///
+    /// The heap can't use the heap to keep track of itself, so the chunk's buddy allocator, its bitmap,
+    /// and its table of aligned free list entries are mapped straight from the frame allocator.
+    unsafe fn add_chunk(&self, number: usize) -> Option<&'static mut Chunk> {
+        let metadata = METADATA_START + (number * METADATA_SIZE) as u64;
+        let aligned = metadata + mem::size_of::<Chunk>() as u64;
+        let bitmap = aligned + (ENTRY_PAGES * mem::size_of::<Link>()) as u64;
+        let bitmap_len = buddy::bitmap_len(CHUNK_PAGES);
+        map_fresh(metadata, (bitmap - metadata) as usize + bitmap_len * mem::size_of::<u64>()).ok()?;
+
+        let links = ChunkLinks {
+            aligned: aligned as *mut Link,
+            entries: (metadata + ENTRIES_OFFSET as u64) as *mut Link,
+            mapped: [0; ENTRY_PAGES / 64],
+        };
+        let bitmap = slice::from_raw_parts_mut(bitmap as *mut u64, bitmap_len);
+        let chunk = metadata as *mut Chunk;
+        ptr::write(chunk, Buddy::new(CHUNK_PAGES, links, bitmap));
+        (*chunk).deallocate_range(0, CHUNK_PAGES);
+        (*self.chunks.get())[number] = chunk;
+        Some(&mut *chunk)
     }
 
-    pub fn free(&self) -> usize {
-        let self_pages = unsafe { &mut *self.pages.get() };
-        let mut free = 0;
-        for page in 0..self_pages.len() * 8 {
-            if !get_bit(self_pages.as_slice(), page) {
-                free += 1;
+    /// Find `count` pages in a row starting at a multiple of 2^`align_order` pages,
+    /// setting up another chunk if none of the ones we have can fit them.
+    unsafe fn allocate_pages(&self, count: usize, align_order: usize) -> Option<usize> {
+        // No chunk could ever fit this, so don't set them all up trying.
+        if count == 0 || buddy::order_of(count).max(align_order) > MAX_ORDER {
+            return None;
+        }
+        for number in 0..CHUNKS {
+            let chunk = match (*self.chunks.get())[number] {
+                chunk if chunk.is_null() => self.add_chunk(number)?,
+                chunk => &mut *chunk,
+            };
+            if let Some(index) = chunk.allocate_range(count, align_order) {
+                return Some(number * CHUNK_PAGES + index);
             }
         }
-        free
+        None
+    }
+
+    unsafe fn deallocate_pages(&self, begin: usize, count: usize) {
+        let chunk = (*self.chunks.get())[begin / CHUNK_PAGES];
+        (*chunk).deallocate_range(begin % CHUNK_PAGES, count);
     }
 
-    // Not very accurate because this includes a lot of reserved/unusable memory.
-    pub fn used(&self) -> usize {
-        let free = self.free();
-        // This line of code crashes QEMU for inexplicable reasons.
-        // I tried to figure out why and failed.
-        let self_pages = unsafe { &*self.pages.get() };
-        free - self_pages.as_slice().len() * 8
+    /// Unmap `count` pages starting from `begin` and give their frames back.
+    unsafe fn release(&self, begin: usize, count: usize) {
+        for index in begin..begin + count {
+            let frame = kernel_page_tables().unmap(Self::page(index)).expect("Heap page wasn't mapped!");
+            frame_allocator().deallocate(frame);
+        }
     }
 }
 
 unsafe impl GlobalAlloc for StandardAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
         let min_pages = num_integer::div_ceil(layout.size(), PAGE_SIZE);
-        let mut pages = None;
-        let self_pages = &mut *self.pages.get();
-        for i in 0..(self_pages.len() * 8) {
-            if !get_bit(&self_pages.as_slice(), i) {
-                pages = match pages {
-                    Some((begin, size)) => Some((begin, size + 1)),
-                    None => Some((i, 1)),
-                };
-
-                if pages.unwrap().1 >= min_pages {
-                    break;
-                }
-            } else {
-                pages = None;
-            }
-        }
-
-        if pages.is_none() || pages.unwrap().1 < min_pages {
-            panic!("Not enough contiguous memory!");
+        let align = buddy::order_of(num_integer::div_ceil(layout.align(), PAGE_SIZE));
+        if frame_allocator().free() < ENTRY_FRAMES {
+            return ptr::null_mut();
         }
-
-        let (begin, size) = pages.unwrap();
-
-        let address = begin * PAGE_SIZE;
-
-        for offset in 0..size {
-            set_bit(self_pages.as_mut_slice(), begin + offset, true);
+        let begin = match self.allocate_pages(min_pages, align) {
+            Some(begin) => begin,
+            None => return ptr::null_mut(),
+        };
+
+        let page = Self::page(begin);
+        match map_fresh(page.start_address().as_u64(), min_pages * PAGE_SIZE) {
+            Ok(()) => page.start_address().as_mut_ptr(),
+            Err(PagingError::OutOfMemory) => {
+                self.deallocate_pages(begin, min_pages);
+                ptr::null_mut()
+            },
+            Err(err) => panic!("Failed to map the heap: {}", err),
         }
-
-        address as *mut u8
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let begin = ptr as usize / PAGE_SIZE;
+        let begin = (ptr as u64 - HEAP_START) as usize / PAGE_SIZE;
         let size = num_integer::div_ceil(layout.size(), PAGE_SIZE);
-        let self_pages = &mut *self.pages.get();
 
-        for offset in 0..size {
-            set_bit(self_pages.as_mut_slice(), begin + offset, false);
-        }
+        self.release(begin, size);
+        self.deallocate_pages(begin, size);
     }
 }
@@ -0,0 +1,240 @@
+use alloc::vec;
+use alloc::vec::Vec;
+use buddy::{Buddy, Link, Links, MAX_ORDER};
+use core::ptr;
+use uefi::table::boot::{MemoryDescriptor, MemoryType};
+use x86_64::PhysAddr;
+use x86_64::structures::paging::{PageSize, PhysFrame, Size4KiB};
+use x86_64::structures::paging::frame::PhysFrameRange;
+
+pub const FRAME_SIZE: u64 = Size4KiB::SIZE;
+
+/// Some devices can't address all of physical memory, so we need to be able to ask for
+/// memory which is low enough for them. Everything else should stay out of the way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Zone {
+    /// Below 1 MiB, for anything that has to be reachable from real mode
+    /// (e.g. the trampoline for starting other processors) and ancient ISA DMA.
+    Low,
+    /// Below 4 GiB, for devices which only support 32-bit DMA.
+    Dma32,
+    /// Anywhere at all.
+    Normal,
+}
+
+const MIB: u64 = 1 << 20;
+const GIB: u64 = 1 << 30;
+
+impl Zone {
+    /// The first frame in the zone, and the first frame after it.
+    fn frames(self) -> (u64, u64) {
+        match self {
+            Zone::Low => (0, MIB / FRAME_SIZE),
+            Zone::Dma32 => (MIB / FRAME_SIZE, 4 * GIB / FRAME_SIZE),
+            Zone::Normal => (4 * GIB / FRAME_SIZE, u64::MAX),
+        }
+    }
+
+    /// The zones to allocate from, in the order we want to use them.
+    /// Allocations are made from the highest zone they're allowed to use first,
+    /// so that the scarce low memory is still there for whoever really needs it.
+    fn fallbacks(self) -> &'static [Zone] {
+        match self {
+            Zone::Low => &[Zone::Low],
+            Zone::Dma32 => &[Zone::Dma32, Zone::Low],
+            Zone::Normal => &[Zone::Normal, Zone::Dma32, Zone::Low],
+        }
+    }
+}
+
+/// Keeps the free list entries of free frames in the frames themselves,
+/// which we can get at through the identity mapping.
+/// Nobody else is using a frame while it's free, so it costs us no memory at all.
+struct FrameLinks {
+    base: u64,
+}
+
+impl FrameLinks {
+    fn link(&self, index: usize) -> *mut Link {
+        ((self.base + index as u64) * FRAME_SIZE) as *mut Link
+    }
+}
+
+impl Links for FrameLinks {
+    fn get(&self, index: usize) -> Link {
+        unsafe { ptr::read(self.link(index)) }
+    }
+
+    fn set(&mut self, index: usize, link: Link) {
+        unsafe { ptr::write(self.link(index), link) }
+    }
+}
+
+/// The frames of one zone.
+struct ZoneAllocator {
+    /// The frame which is page 0 to the buddy allocator.
+    /// This is the start of the zone rounded down to the largest block size,
+    /// so that blocks which are aligned in the allocator are aligned in physical memory too.
+    base: u64,
+    start: u64,
+    end: u64,
+    buddy: Buddy<'static, FrameLinks>,
+}
+
+impl ZoneAllocator {
+    /// The part of the range of frames from `start` to `end` that's in this zone, if any,
+    /// relative to `base`.
+    fn intersect(&self, start: u64, end: u64) -> Option<(usize, usize)> {
+        let start = start.max(self.start);
+        let end = end.min(self.end);
+        if start >= end {
+            return None;
+        }
+        Some(((start - self.base) as usize, (end - start) as usize))
+    }
+}
+
+/// Keeps track of which frames of physical memory are free.
+///
+/// Every frame is 4 KiB, but we can allocate 2 MiB and 1 GiB frames (for huge pages)
+/// and contiguous ranges of frames (for DMA), which are all just different sizes of buddy block.
+pub struct FrameAllocator {
+    zones: [ZoneAllocator; 3],
+}
+
+impl FrameAllocator {
+    /// Allocates a new allocator data structure sufficient
+    /// to use the physical memory provided in the memory map.
+    /// With what allocator does the allocator get allocated? The UEFI allocator.
+    /// This does *not* pre-populate the allocator with usage data;
+    /// by default, it will behave as though every frame were allocated.
+    /// Use `populate` to fill the allocator with actual data
+    /// using the map that UEFI provides when you exit boot services.
+    pub fn new<'buf>(mmap: &mut impl ExactSizeIterator<Item = &'buf MemoryDescriptor>) -> FrameAllocator {
+        // Try to find the largest physical address
+        // and create enough zones to allocate that much memory.
+        let greatest_physical_frame =
+            mmap.map(|d| num_integer::div_ceil(d.phys_start, FRAME_SIZE) + d.page_count).max().unwrap();
+
+        let zone = |zone: Zone| {
+            let (start, end) = zone.frames();
+            let end = end.min(greatest_physical_frame);
+            let base = start / (1 << MAX_ORDER) * (1 << MAX_ORDER);
+            let len = if start < end { (end - base) as usize } else { 0 };
+            // This is allocated by the UEFI allocator, so it must never be freed.
+            // It's only a couple of bits per frame, since the free lists are kept in the free frames.
+            let bitmap = Vec::leak(vec![0; buddy::bitmap_len(len)]);
+            ZoneAllocator {
+                base: base,
+                start: start,
+                end: end,
+                buddy: Buddy::new(len, FrameLinks { base: base }, bitmap),
+            }
+        };
+
+        FrameAllocator {
+            zones: [zone(Zone::Low), zone(Zone::Dma32), zone(Zone::Normal)],
+        }
+    }
+
+    fn zone(&mut self, zone: Zone) -> &mut ZoneAllocator {
+        &mut self.zones[zone as usize]
+    }
+
+    /// The zone allocator that `frame` belongs to.
+    fn zone_of(&mut self, frame: u64) -> &mut ZoneAllocator {
+        self.zones.iter_mut()
+            .find(|zone| zone.start <= frame && frame < zone.end)
+            .expect("Frame is outside of physical memory!")
+    }
+
+    /// Mark the frames from `entry` as free.
+    fn free_entry(&mut self, entry: &MemoryDescriptor) {
+        // Even if the zero address is valid memory, we *definitely* don't want to allocate it.
+        let start = (entry.phys_start / FRAME_SIZE).max(1);
+        let end = entry.phys_start / FRAME_SIZE + entry.page_count;
+        for zone in self.zones.iter_mut() {
+            if let Some((index, count)) = zone.intersect(start, end) {
+                zone.buddy.deallocate_range(index, count);
+            }
+        }
+    }
+
+    /// Mark all of the memory that nobody is using as free for allocations.
+    ///
+    /// Boot services memory isn't included, because we're still using some of it after exiting
+    /// boot services (UEFI's page tables, for a start). Use `reclaim` for that once we're done with it.
+    /// The free lists are kept in the free frames, so this writes to them.
+    pub fn populate<'buf>(&mut self, mmap: &mut impl ExactSizeIterator<Item = &'buf MemoryDescriptor>) {
+        for entry in mmap {
+            if entry.ty == MemoryType::CONVENTIONAL {
+                self.free_entry(entry);
+            }
+        }
+    }
+
+    /// Mark the memory that boot services were using as free.
+    ///
+    /// This is unsafe because freed frames are overwritten immediately, so we had better have replaced
+    /// everything UEFI left us with (the page tables, GDT, IDT, and stack) first.
+    pub unsafe fn reclaim(&mut self, mmap: &[MemoryDescriptor]) {
+        for entry in mmap {
+            if entry.ty == MemoryType::BOOT_SERVICES_CODE || entry.ty == MemoryType::BOOT_SERVICES_DATA {
+                self.free_entry(entry);
+            }
+        }
+    }
+
+    /// The number of frames which are free (of any zone).
+    pub fn free(&self) -> usize {
+        self.zones.iter().map(|zone| zone.buddy.free()).sum()
+    }
+
+    /// Allocate a frame of any size from `zone`. The frame is *not* zeroed.
+    pub fn allocate<S: PageSize>(&mut self, zone: Zone) -> Option<PhysFrame<S>> {
+        let order = buddy::order_of((S::SIZE / FRAME_SIZE) as usize);
+        zone.fallbacks().iter().find_map(|&zone| {
+            let zone = self.zone(zone);
+            let index = zone.buddy.allocate(order)?;
+            Some(PhysFrame::containing_address(PhysAddr::new((zone.base + index as u64) * FRAME_SIZE)))
+        })
+    }
+
+    pub fn deallocate<S: PageSize>(&mut self, frame: PhysFrame<S>) {
+        let order = buddy::order_of((S::SIZE / FRAME_SIZE) as usize);
+        let zone = self.zone_of(frame.start_address().as_u64() / FRAME_SIZE);
+        let index = frame.start_address().as_u64() / FRAME_SIZE - zone.base;
+        zone.buddy.deallocate(index as usize, order);
+    }
+
+    /// Allocate `count` physically contiguous 4 KiB frames from `zone`, e.g. for a DMA buffer.
+    pub fn allocate_range(&mut self, count: usize, zone: Zone) -> Option<PhysFrameRange> {
+        zone.fallbacks().iter().find_map(|&zone| {
+            let zone = self.zone(zone);
+            let index = zone.buddy.allocate_range(count, 0)?;
+            let start = PhysFrame::containing_address(PhysAddr::new((zone.base + index as u64) * FRAME_SIZE));
+            Some(PhysFrame::range(start, start + count as u64))
+        })
+    }
+
+    pub fn deallocate_range(&mut self, range: PhysFrameRange) {
+        let zone = self.zone_of(range.start.start_address().as_u64() / FRAME_SIZE);
+        let index = range.start.start_address().as_u64() / FRAME_SIZE - zone.base;
+        zone.buddy.deallocate_range(index as usize, (range.end - range.start) as usize);
+    }
+}
+
+static mut FRAME_ALLOCATOR: Option<FrameAllocator> = None;
+
+/// The allocator for all of physical memory. Only available after `init`.
+pub fn frame_allocator() -> &'static mut FrameAllocator {
+    unsafe { FRAME_ALLOCATOR.as_mut().expect("The frame allocator hasn't been set up yet!") }
+}
+
+/// Make `allocator` the allocator for all of physical memory.
+///
+/// This is unsafe because the allocator had better be populated with the final memory map,
+/// or we'll hand out frames that boot services are still using.
+pub unsafe fn init(allocator: FrameAllocator) {
+    FRAME_ALLOCATOR = Some(allocator);
+}
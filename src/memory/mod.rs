@@ -1 +1,3 @@
 pub mod allocator;
+pub mod frame;
+pub mod paging;
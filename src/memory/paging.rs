@@ -0,0 +1,363 @@
+pub mod kernel_image;
+pub mod memory_attributes;
+
+use core::fmt;
+use crate::arch::x86_64::stack;
+use crate::driver::graphic_display::framebuffer::FramebufferInfo;
+use crate::memory::frame::{Zone, frame_allocator};
+use self::kernel_image::KernelImage;
+use self::memory_attributes::MemoryAttributesTable;
+use uefi::table::boot::{MemoryDescriptor, MemoryType};
+use x86_64::{PhysAddr, VirtAddr};
+use x86_64::registers::control::{Cr0, Cr0Flags, Cr3};
+use x86_64::registers::model_specific::{Efer, EferFlags};
+use x86_64::structures::paging::{
+    FrameAllocator, Mapper, OffsetPageTable, Page, PageSize, PageTable, PageTableFlags, PhysFrame,
+    Size1GiB, Size2MiB, Size4KiB,
+};
+use x86_64::structures::paging::mapper::{MapToError, TranslateError, UnmapError};
+
+// UEFI leaves us with all of physical memory identity mapped, and so do we,
+// because it's by far the simplest way for the kernel to get at physical memory
+// (including the page tables themselves, which is why the `OffsetPageTable` offset is zero).
+// What we gain by having our own page tables is control over permissions:
+// the kernel's code isn't writable, its data isn't executable,
+// and dereferencing a null pointer faults instead of quietly reading the first page of memory.
+
+pub const PAGE_SIZE: u64 = Size4KiB::SIZE;
+
+/// Takes frames for page tables from the frame allocator.
+///
+/// All of physical memory is identity mapped,
+/// so the address of the frame is both where the table is and how we get to it.
+/// We don't zero the frames because the mapper zeroes new tables itself.
+struct PhysicalFrames;
+
+unsafe impl FrameAllocator<Size4KiB> for PhysicalFrames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        frame_allocator().allocate(Zone::Normal)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PagingError {
+    /// We couldn't allocate a frame for a new page table.
+    OutOfMemory,
+    AlreadyMapped,
+    NotMapped,
+    /// The page is part of a larger page, which can't be mapped or unmapped piecemeal.
+    HugePage,
+}
+
+impl fmt::Display for PagingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PagingError::OutOfMemory =>
+                write!(f, "out of memory for page tables"),
+            PagingError::AlreadyMapped =>
+                write!(f, "page is already mapped"),
+            PagingError::NotMapped =>
+                write!(f, "page is not mapped"),
+            PagingError::HugePage =>
+                write!(f, "page is part of a huge page"),
+        }
+    }
+}
+
+impl<S: PageSize> From<MapToError<S>> for PagingError {
+    fn from(err: MapToError<S>) -> PagingError {
+        match err {
+            MapToError::FrameAllocationFailed => PagingError::OutOfMemory,
+            MapToError::ParentEntryHugePage => PagingError::HugePage,
+            MapToError::PageAlreadyMapped(_) => PagingError::AlreadyMapped,
+        }
+    }
+}
+
+impl From<UnmapError> for PagingError {
+    fn from(err: UnmapError) -> PagingError {
+        match err {
+            UnmapError::ParentEntryHugePage => PagingError::HugePage,
+            UnmapError::PageNotMapped => PagingError::NotMapped,
+            // We never map frames that don't exist, so this would mean the tables are corrupt.
+            UnmapError::InvalidFrameAddress(addr) => panic!("Invalid frame address in page table: {:?}", addr),
+        }
+    }
+}
+
+/// A 4-level page table hierarchy.
+pub struct PageTables {
+    pml4: PhysFrame,
+    mapper: OffsetPageTable<'static>,
+    /// Whether these are the page tables the CPU is using,
+    /// in which case we have to flush the TLB whenever we change them.
+    active: bool,
+}
+
+impl PageTables {
+    /// Allocates page tables which don't map anything.
+    pub fn new() -> PageTables {
+        let pml4 = PhysicalFrames.allocate_frame().expect("Failed to allocate a page table!");
+        let table = unsafe { &mut *(pml4.start_address().as_u64() as *mut PageTable) };
+        table.zero();
+        PageTables {
+            pml4: pml4,
+            mapper: unsafe { OffsetPageTable::new(table, VirtAddr::new(0)) },
+            active: false,
+        }
+    }
+
+    /// Map `page` to `frame`. The page is always mapped present, whatever `flags` says.
+    ///
+    /// This is unsafe because nothing stops you from mapping the same frame twice,
+    /// or a frame that something else owns, or over memory that the kernel is using.
+    pub unsafe fn map(&mut self, page: Page, frame: PhysFrame, flags: PageTableFlags) -> Result<(), PagingError> {
+        let flush = self.mapper.map_to(page, frame, flags | PageTableFlags::PRESENT, &mut PhysicalFrames)?;
+        if self.active {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+        Ok(())
+    }
+
+    /// Unmap `page`, returning the frame it was mapped to.
+    /// The frame is not freed; that's up to whoever mapped it.
+    ///
+    /// This is unsafe because anything still using the page will fault.
+    pub unsafe fn unmap(&mut self, page: Page) -> Result<PhysFrame, PagingError> {
+        let (frame, flush) = self.mapper.unmap(page)?;
+        if self.active {
+            flush.flush();
+        } else {
+            flush.ignore();
+        }
+        Ok(frame)
+    }
+
+    /// The physical address that `addr` is mapped to, if it's mapped at all.
+    pub fn translate(&self, addr: VirtAddr) -> Option<PhysAddr> {
+        // We don't know what size of page the address is in until we try.
+        match self.translate_with::<Size4KiB>(addr) {
+            Err(TranslateError::ParentEntryHugePage) => {},
+            result => return result.ok(),
+        }
+        match self.translate_with::<Size2MiB>(addr) {
+            Err(TranslateError::ParentEntryHugePage) => {},
+            result => return result.ok(),
+        }
+        self.translate_with::<Size1GiB>(addr).ok()
+    }
+
+    fn translate_with<S: PageSize>(&self, addr: VirtAddr) -> Result<PhysAddr, TranslateError>
+        where OffsetPageTable<'static>: Mapper<S> {
+        let page = Page::<S>::containing_address(addr);
+        let frame = self.mapper.translate_page(page)?;
+        Ok(frame.start_address() + (addr - page.start_address()))
+    }
+
+    /// Identity map every page between `start` and `end` which isn't mapped already.
+    ///
+    /// If `huge_pages` is set, page-aligned 2 MiB blocks get a single huge page, which saves us
+    /// a lot of page tables (and TLB entries) when mapping all of physical memory.
+    fn identity_map(&mut self, start: u64, end: u64, flags: PageTableFlags, huge_pages: bool)
+        -> Result<(), PagingError> {
+        let flags = flags | PageTableFlags::PRESENT;
+        let mut addr = start / PAGE_SIZE * PAGE_SIZE;
+        while addr < end {
+            if huge_pages && addr % Size2MiB::SIZE == 0 && end - addr >= Size2MiB::SIZE {
+                let page = Page::<Size2MiB>::containing_address(VirtAddr::new(addr));
+                let frame = PhysFrame::<Size2MiB>::containing_address(PhysAddr::new(addr));
+                match unsafe { self.mapper.map_to(page, frame, flags, &mut PhysicalFrames) } {
+                    Ok(flush) => {
+                        flush.ignore();
+                        addr += Size2MiB::SIZE;
+                        continue;
+                    },
+                    // Some of this block is already mapped using small pages (e.g. the kernel),
+                    // so we have to map the rest of it using small pages too.
+                    Err(MapToError::PageAlreadyMapped(_)) => {},
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+            let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(addr));
+            match unsafe { self.mapper.map_to(page, frame, flags, &mut PhysicalFrames) } {
+                Ok(flush) => flush.ignore(),
+                // Anything that's already mapped was mapped on purpose, so leave it alone.
+                Err(MapToError::PageAlreadyMapped(_)) => {},
+                // Two of the ranges we were asked to map overlap (e.g. the framebuffer is in the memory map),
+                // and the first one got a huge page. Splitting it up isn't worth it,
+                // but we can't quietly give the rest of the range different permissions than it asked for.
+                Err(MapToError::ParentEntryHugePage) => {
+                    let permissions = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+                    match self.huge_page_flags(VirtAddr::new(addr)) {
+                        Some(existing) if existing & permissions == flags & permissions => {},
+                        _ => return Err(PagingError::HugePage),
+                    }
+                },
+                Err(err) => return Err(err.into()),
+            }
+            addr += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    /// The flags of the huge page (2 MiB or 1 GiB) that `addr` is in, if it's in one.
+    fn huge_page_flags(&self, addr: VirtAddr) -> Option<PageTableFlags> {
+        // The page tables are identity mapped, so we can walk them ourselves.
+        let table = |frame: PhysAddr| unsafe { &*(frame.as_u64() as *const PageTable) };
+        let pml4 = table(self.pml4.start_address());
+        let entry = &pml4[addr.p4_index()];
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        let entry = &table(entry.addr())[addr.p3_index()];
+        if entry.flags().contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE) {
+            return Some(entry.flags());
+        }
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return None;
+        }
+        let entry = &table(entry.addr())[addr.p2_index()];
+        if entry.flags().contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE) {
+            Some(entry.flags())
+        } else {
+            None
+        }
+    }
+
+    /// Identity map the kernel image with the permissions each of its sections asks for.
+    ///
+    /// Sections are normally page-aligned, but if two of them share a page,
+    /// the page gets the permissions of both.
+    /// The headers and any padding between sections are read-only.
+    fn map_kernel(&mut self, image: KernelImage) -> Result<(), PagingError> {
+        let mut addr = image.start();
+        while addr < image.start() + image.size() {
+            let mut flags = PageTableFlags::PRESENT | PageTableFlags::NO_EXECUTE;
+            for section in image.sections() {
+                if section.start < addr + PAGE_SIZE && addr < section.start + section.size {
+                    if section.writable {
+                        flags |= PageTableFlags::WRITABLE;
+                    }
+                    if section.executable {
+                        flags.remove(PageTableFlags::NO_EXECUTE);
+                    }
+                }
+            }
+
+            let page = Page::<Size4KiB>::containing_address(VirtAddr::new(addr));
+            let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(addr));
+            unsafe { self.mapper.map_to(page, frame, flags, &mut PhysicalFrames)?.ignore(); }
+            addr += PAGE_SIZE;
+        }
+        Ok(())
+    }
+
+    /// Switch the CPU to these page tables.
+    ///
+    /// This is unsafe because everything the CPU is currently using
+    /// (the code we're running, the stack, the GDT and IDT...) had better still be mapped.
+    unsafe fn activate(&mut self) {
+        let (_, flags) = Cr3::read();
+        Cr3::write(self.pml4, flags);
+        self.active = true;
+    }
+}
+
+static mut KERNEL_PAGE_TABLES: Option<PageTables> = None;
+
+/// The page tables the kernel runs on. Only available after `init`.
+pub fn kernel_page_tables() -> &'static mut PageTables {
+    unsafe { KERNEL_PAGE_TABLES.as_mut().expect("The kernel page tables haven't been set up yet!") }
+}
+
+/// Build the kernel's own page tables and switch to them.
+///
+/// This must be done after exiting boot services, because we take ownership of
+/// all of memory (boot services memory included), and after the frame allocator is set up,
+/// because that's where the page tables come from.
+pub unsafe fn init(
+    mmap: &[MemoryDescriptor],
+    attributes: Option<MemoryAttributesTable>,
+    framebuffer: Option<FramebufferInfo>,
+) {
+    let mut tables = PageTables::new();
+
+    // The kernel is mapped first so that the identity mapping of the rest of memory skips it,
+    // rather than mapping it writable and executable.
+    let image = KernelImage::get();
+    match image {
+        Some(image) => tables.map_kernel(image).expect("Failed to map the kernel!"),
+        None => log::warn!("Failed to read the kernel's headers; all of its sections will be writable and executable."),
+    }
+
+    // Runtime services memory is mapped next, if the firmware told us which of it is code,
+    // so that the runtime drivers' data isn't executable and their code isn't writable.
+    // These regions are small and don't line up with 2 MiB blocks of the memory map,
+    // so they get small pages, and the rest of their memory map entry gets mapped around them.
+    match attributes {
+        Some(table) => {
+            for region in table.regions() {
+                let mut flags = PageTableFlags::empty();
+                if region.writable {
+                    flags |= PageTableFlags::WRITABLE;
+                }
+                if !region.executable {
+                    flags |= PageTableFlags::NO_EXECUTE;
+                }
+                tables.identity_map(region.start, region.start + region.size, flags, false)
+                    .expect("Failed to map runtime services!");
+            }
+        },
+        None => log::warn!("No memory attributes table; runtime services code will be read-only."),
+    }
+
+    for entry in mmap {
+        let flags = match entry.ty {
+            // Without the memory attributes table, we can't tell the runtime drivers' code from their data,
+            // and we'd rather a runtime service fault writing to its data than have W+X memory.
+            MemoryType::RUNTIME_SERVICES_CODE =>
+                PageTableFlags::empty(),
+            MemoryType::LOADER_CODE if image.is_none() =>
+                PageTableFlags::WRITABLE,
+            MemoryType::MMIO | MemoryType::MMIO_PORT_SPACE =>
+                PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE | PageTableFlags::NO_CACHE,
+            _ =>
+                PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE,
+        };
+        // The first page stays unmapped so that null pointer dereferences fault.
+        let start = entry.phys_start.max(PAGE_SIZE);
+        let end = entry.phys_start + entry.page_count * PAGE_SIZE;
+        if start < end {
+            tables.identity_map(start, end, flags, true).expect("Failed to map physical memory!");
+        }
+    }
+
+    // The framebuffer usually isn't in the memory map at all.
+    // We leave caching to the MTRRs, which the firmware sets up to write-combine video memory.
+    if let Some(info) = framebuffer {
+        let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
+        tables.identity_map(info.base(), info.base() + info.size() as u64, flags, true)
+            .expect("Failed to map the framebuffer!");
+    }
+
+    // The kernel stack's guard page is part of the kernel image, so it was mapped along with the rest of it.
+    // Without the kernel's headers, it's probably in a huge page, so it'll have to do without.
+    match tables.unmap(Page::containing_address(stack::guard_page())) {
+        Ok(_) => {},
+        Err(err) => log::warn!("Failed to unmap the kernel stack's guard page: {}", err),
+    }
+
+    // UEFI doesn't necessarily enable either of these.
+    // Without NXE, the NO_EXECUTE bit is reserved, so every page we've mapped would fault,
+    // and without WP, the kernel can write to read-only pages anyway.
+    Efer::update(|flags| flags.insert(EferFlags::NO_EXECUTE_ENABLE));
+    Cr0::update(|flags| flags.insert(Cr0Flags::WRITE_PROTECT));
+
+    tables.activate();
+    KERNEL_PAGE_TABLES = Some(tables);
+    log::debug!("Switched to the kernel page tables.");
+}
@@ -0,0 +1,96 @@
+// Finds the sections of the kernel image in memory so we can map them with the right permissions.
+//
+// UEFI applications are PE32+ executables, and UEFI loads them at whatever address it likes,
+// applying relocations so that they run at that address (identity mapped).
+// The headers are loaded along with the rest of the image, so we can just read them
+// instead of having to ask UEFI where we ended up.
+use core::ptr;
+
+extern "C" {
+    // Defined by the linker (lld-link, like MSVC's) to be the address of the image's DOS header.
+    static __ImageBase: u8;
+}
+
+const PE_SIGNATURE: u32 = 0x0000_4550; // b"PE\0\0"
+const PE32_PLUS_MAGIC: u16 = 0x020B;
+const SECTION_HEADER_SIZE: usize = 40;
+const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Section {
+    /// The address of the first byte of the section.
+    pub start: u64,
+    /// The size of the section once loaded, which includes any zero-filled part (e.g. `.bss`).
+    pub size: u64,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct KernelImage {
+    base: *const u8,
+    size: u64,
+    sections: *const u8,
+    section_count: usize,
+}
+
+unsafe fn read_u16(ptr: *const u8) -> u16 {
+    u16::from_le(ptr::read_unaligned(ptr as *const u16))
+}
+
+unsafe fn read_u32(ptr: *const u8) -> u32 {
+    u32::from_le(ptr::read_unaligned(ptr as *const u32))
+}
+
+impl KernelImage {
+    /// Reads the headers of the running kernel.
+    ///
+    /// This can only fail if the kernel was linked wrong,
+    /// but we'd rather fall back to mapping the image writable and executable than not boot.
+    pub fn get() -> Option<KernelImage> {
+        unsafe {
+            let base = &__ImageBase as *const u8;
+            let pe = base.add(read_u32(base.add(0x3C)) as usize);
+            if read_u32(pe) != PE_SIGNATURE {
+                return None;
+            }
+            let coff = pe.add(4);
+            let section_count = read_u16(coff.add(2)) as usize;
+            let optional_header_size = read_u16(coff.add(16)) as usize;
+            let optional = coff.add(20);
+            if read_u16(optional) != PE32_PLUS_MAGIC {
+                return None;
+            }
+            Some(KernelImage {
+                base: base,
+                size: read_u32(optional.add(56)) as u64,
+                sections: optional.add(optional_header_size),
+                section_count: section_count,
+            })
+        }
+    }
+
+    /// The address the kernel was loaded at, which is where its headers are.
+    pub fn start(&self) -> u64 {
+        self.base as u64
+    }
+
+    /// The size of the whole image in memory, including the headers and padding between sections.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn sections(&self) -> impl Iterator<Item = Section> + '_ {
+        (0..self.section_count).map(move |i| unsafe {
+            let header = self.sections.add(i * SECTION_HEADER_SIZE);
+            let characteristics = read_u32(header.add(36));
+            Section {
+                start: self.start() + read_u32(header.add(12)) as u64,
+                size: read_u32(header.add(8)) as u64,
+                writable: characteristics & SCN_MEM_WRITE != 0,
+                executable: characteristics & SCN_MEM_EXECUTE != 0,
+            }
+        })
+    }
+}
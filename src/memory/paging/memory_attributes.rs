@@ -0,0 +1,77 @@
+// Finds out which parts of runtime services memory are code and which are data.
+//
+// The memory map only says that memory belongs to runtime services code,
+// but the runtime drivers in it are PE images with data sections of their own,
+// so mapping it read-only would break runtime services and mapping it writable would make it W+X.
+// Newer firmware (UEFI 2.6 and up) publishes a memory attributes table in the configuration table,
+// which splits runtime services memory up into regions that are either read-only or non-executable.
+use core::ptr;
+use uefi::Guid;
+use uefi::table::{Boot, SystemTable};
+
+const MEMORY_ATTRIBUTES_TABLE_GUID: Guid =
+    Guid::from_values(0xdcfa_911d, 0x26eb, 0x469f, 0xa220, [0x38, 0xb7, 0xdc, 0x46, 0x12, 0x20]);
+const HEADER_SIZE: usize = 16;
+/// The size of the fields of a memory descriptor that we read.
+const DESCRIPTOR_SIZE: usize = 40;
+const EFI_MEMORY_XP: u64 = 0x4000;
+const EFI_MEMORY_RO: u64 = 0x20000;
+const PAGE_SIZE: u64 = 4096;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Region {
+    pub start: u64,
+    pub size: u64,
+    pub writable: bool,
+    pub executable: bool,
+}
+
+#[derive(Clone, Copy)]
+pub struct MemoryAttributesTable {
+    entries: *const u8,
+    entry_count: usize,
+    entry_size: usize,
+}
+
+unsafe fn read_u32(ptr: *const u8) -> u32 {
+    u32::from_le(ptr::read_unaligned(ptr as *const u32))
+}
+
+unsafe fn read_u64(ptr: *const u8) -> u64 {
+    u64::from_le(ptr::read_unaligned(ptr as *const u64))
+}
+
+impl MemoryAttributesTable {
+    /// Finds the memory attributes table, if the firmware has one.
+    ///
+    /// The table itself is in runtime memory, so it's still there after we exit boot services,
+    /// but finding it means going through the configuration table, which is easiest before.
+    pub fn find(st: &SystemTable<Boot>) -> Option<MemoryAttributesTable> {
+        let entry = st.config_table().iter().find(|entry| entry.guid == MEMORY_ATTRIBUTES_TABLE_GUID)?;
+        unsafe {
+            let header = entry.address as *const u8;
+            let entry_size = read_u32(header.add(8)) as usize;
+            if entry_size < DESCRIPTOR_SIZE {
+                return None;
+            }
+            Some(MemoryAttributesTable {
+                entries: header.add(HEADER_SIZE),
+                entry_count: read_u32(header.add(4)) as usize,
+                entry_size: entry_size,
+            })
+        }
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = Region> + '_ {
+        (0..self.entry_count).map(move |i| unsafe {
+            let descriptor = self.entries.add(i * self.entry_size);
+            let attributes = read_u64(descriptor.add(32));
+            Region {
+                start: read_u64(descriptor.add(8)),
+                size: read_u64(descriptor.add(24)) * PAGE_SIZE,
+                writable: attributes & EFI_MEMORY_RO == 0,
+                executable: attributes & EFI_MEMORY_XP == 0,
+            }
+        })
+    }
+}
@@ -0,0 +1,188 @@
+// What happens when the kernel panics: the panic is logged to the serial port and,
+// if there's a framebuffer, a panic screen with the message, the location of the panic,
+// the registers, and the most recent log messages is drawn over whatever was on the screen.
+//
+// A panic can come from anywhere, including the allocator or the console, so nothing here
+// allocates or touches the console: the font and framebuffer are set aside ahead of time
+// (see `init`) and we draw straight to the framebuffer, one glyph at a time.
+
+use core::fmt::{self, Write};
+use crate::arch::x86_64::registers::Registers;
+use crate::driver::graphic_display::GraphicDisplay;
+use crate::driver::graphic_display::framebuffer::{Framebuffer, FramebufferInfo};
+use crate::graphics::color::{COLOR_WHITE, RGB};
+use crate::graphics::font::Font;
+use crate::graphics::font::scaled::ScaledGlyph;
+use crate::logger;
+
+const PANIC_BG: RGB = RGB::new(0x70, 0x10, 0x10);
+const PANIC_FG: RGB = COLOR_WHITE;
+/// The recent log is dimmer than the panic itself, since it's less important.
+const LOG_FG: RGB = RGB::new(0xD0, 0xB0, 0xB0);
+
+/// Where a panic happened in the source code.
+#[derive(Copy, Clone)]
+pub struct Location<'a> {
+    pub file: &'a str,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Location<'_> {
+    pub fn new<'a>(file: &'a str, line: u32, column: u32) -> Location<'a> {
+        Location { file: file, line: line, column: column }
+    }
+}
+
+impl fmt::Display for Location<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+/// Everything we need to draw the panic screen, reserved before we need it.
+struct PanicScreen {
+    framebuffer: FramebufferInfo,
+    font: &'static dyn Font,
+    scale: usize,
+}
+
+static mut PANIC_SCREEN: Option<PanicScreen> = None;
+static mut PANICKING: bool = false;
+
+/// Draw the panic screen on this framebuffer with this font if the kernel panics.
+///
+/// Unsafe: the framebuffer must stay mapped for as long as the kernel runs.
+/// Whatever else draws to it will be interrupted by the panic and never draw to it again.
+pub unsafe fn init(framebuffer: FramebufferInfo, font: &'static dyn Font, scale: usize) {
+    PANIC_SCREEN = Some(PanicScreen {
+        framebuffer: framebuffer,
+        font: font,
+        scale: scale.max(1),
+    });
+}
+
+/// Report a panic and halt. This is what the `panic!` macro and the panic handler call.
+pub fn panic(message: fmt::Arguments, location: Option<Location>) -> ! {
+    let registers = Registers::capture();
+    unsafe {
+        // If we panic while handling a panic, the first panic is what matters,
+        // and whatever went wrong will probably just go wrong again.
+        if PANICKING {
+            log::error!("Panicked while panicking: {}", message);
+            crate::arch::x86_64::halt();
+        }
+        PANICKING = true;
+    }
+
+    logger::detach_console();
+    // Copy the log before we add the panic to it, since the panic screen shows that separately.
+    let recent_log = logger::recent_log();
+    match location {
+        Some(location) => log::error!("Panicked at {}: {}", location, message),
+        None => log::error!("Panicked: {}", message),
+    }
+    log::error!("Registers:\n{}", registers);
+
+    if let Some(screen) = unsafe { &PANIC_SCREEN } {
+        let mut display = unsafe { Framebuffer::new(screen.framebuffer) };
+        display.clear(PANIC_BG);
+        let mut writer = ScreenWriter::new(&mut display, screen.font, screen.scale);
+        let _ = writeln!(writer, "The kernel panicked!");
+        let _ = writeln!(writer);
+        let _ = writeln!(writer, "{}", message);
+        if let Some(location) = location {
+            let _ = writeln!(writer, "at {}", location);
+        }
+        let _ = writeln!(writer);
+        let _ = write!(writer, "{}", registers);
+        let _ = writeln!(writer);
+
+        writer.fg = LOG_FG;
+        let _ = writeln!(writer, "Recent log messages:");
+        // Show as many of the most recent messages as fit.
+        // (Long messages may wrap, so this isn't exact; the oldest may be cut off at the bottom.)
+        let room = writer.rows.saturating_sub(writer.y);
+        let skip = recent_log.lines().count().saturating_sub(room);
+        for line in recent_log.lines().skip(skip) {
+            let _ = writeln!(writer, "{}", line);
+        }
+    }
+
+    // FIXME: Panic shouldn't depend on an architecture-specific function.
+    crate::arch::x86_64::halt()
+}
+
+/// Draws text to the screen a character at a time, like a very dumb terminal.
+/// Text which goes past the right edge of the screen wraps, and text past the bottom is dropped.
+struct ScreenWriter<'a> {
+    display: &'a mut Framebuffer,
+    font: &'a dyn Font,
+    scale: usize,
+    fg: RGB,
+    columns: usize,
+    rows: usize,
+    /// The cell the next character goes in. There's a margin of one cell around the screen.
+    x: usize,
+    y: usize,
+}
+
+impl ScreenWriter<'_> {
+    fn new<'a>(display: &'a mut Framebuffer, font: &'a dyn Font, scale: usize) -> ScreenWriter<'a> {
+        let (width, height) = display.resolution();
+        let (cell_width, cell_height) = font.bounding_box();
+        let columns = width / (cell_width * scale).max(1);
+        let rows = height / (cell_height * scale).max(1);
+        ScreenWriter {
+            display: display,
+            font: font,
+            scale: scale,
+            fg: PANIC_FG,
+            columns: columns.saturating_sub(2),
+            rows: rows.saturating_sub(2),
+            x: 0,
+            y: 0,
+        }
+    }
+
+    fn newline(&mut self) {
+        self.x = 0;
+        self.y += 1;
+    }
+
+    fn putc(&mut self, c: char) {
+        if c == '\n' {
+            self.newline();
+            return;
+        }
+        if self.x >= self.columns {
+            self.newline();
+        }
+        if self.y >= self.rows {
+            return;
+        }
+
+        let glyph = match self.font.lookup(c).or_else(|| self.font.lookup('?')) {
+            Some(glyph) => glyph,
+            None => return,
+        };
+        let (cell_width, cell_height) = self.font.bounding_box();
+        let bounding_box = (cell_width * self.scale, cell_height * self.scale);
+        let x = (self.x + 1) * bounding_box.0;
+        let y = (self.y + 1) * bounding_box.1;
+        unsafe {
+            // The margin means the cell is always entirely on the screen.
+            self.display.draw_glyph_blended(bounding_box, x, y, self.fg, PANIC_BG, &ScaledGlyph::new(&glyph, self.scale));
+        }
+        self.x += 1;
+    }
+}
+
+impl Write for ScreenWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.putc(c);
+        }
+        Ok(())
+    }
+}
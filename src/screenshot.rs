@@ -0,0 +1,132 @@
+// Screenshots sent over the serial port, for debugging graphics on machines (or headless VMs)
+// whose screens we can't see. `tools/screenshot.py` extracts them from a log of the serial output.
+//
+// The screenshot is a binary PPM image, but the serial port is shared with the kernel log
+// and is usually connected to a terminal, so we can't just dump binary data onto it.
+// Instead, it's sent as base64 between a header and a footer line:
+//
+//     === bootproof screenshot begin: ppm <length> ===
+//     <base64, 76 characters per line>
+//     === bootproof screenshot end: crc32 <crc32 of the decoded data, as 8 hex digits> ===
+//
+// Everything is streamed straight from the display, so this doesn't allocate,
+// and can be used even when the heap isn't working.
+
+use core::fmt::Write;
+use crate::checksum::Crc32;
+use crate::driver::graphic_display::GraphicDisplay;
+use crate::driver::tty::Tty;
+use crate::driver::tty::serial::SerialTty;
+use crate::graphics::color::Color;
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// The length of each line of base64, which is the same as MIME's, for no particular reason.
+const LINE_LENGTH: usize = 76;
+
+/// Encodes bytes as base64 and sends them to a TTY as they come in, broken into lines.
+struct Base64Writer<'t> {
+    tty: &'t mut dyn Tty,
+    /// Bytes which haven't been encoded yet, since base64 encodes three bytes at a time.
+    pending: [u8; 3],
+    pending_len: usize,
+    line_len: usize,
+    crc: Crc32,
+}
+
+impl Base64Writer<'_> {
+    fn new<'t>(tty: &'t mut dyn Tty) -> Base64Writer<'t> {
+        Base64Writer {
+            tty: tty,
+            pending: [0; 3],
+            pending_len: 0,
+            line_len: 0,
+            crc: Crc32::new(),
+        }
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        self.crc.update(bytes);
+        for &byte in bytes {
+            self.pending[self.pending_len] = byte;
+            self.pending_len += 1;
+            if self.pending_len == 3 {
+                self.encode_pending();
+            }
+        }
+    }
+
+    /// Encode the pending bytes, padding the output if there are fewer than three of them.
+    fn encode_pending(&mut self) {
+        let [a, b, c] = self.pending;
+        let group = (a as u32) << 16 | (b as u32) << 8 | c as u32;
+        for i in 0..4 {
+            // Two pending bytes encode to three characters, and one byte encodes to two.
+            let c = if i <= self.pending_len {
+                BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char
+            } else {
+                '='
+            };
+            self.tty.putc(c);
+        }
+        self.pending = [0; 3];
+        self.pending_len = 0;
+
+        self.line_len += 4;
+        if self.line_len >= LINE_LENGTH {
+            self.tty.putc('\n');
+            self.line_len = 0;
+        }
+    }
+
+    /// Encode whatever is left and end the last line. Returns the CRC of everything written.
+    fn finish(mut self) -> u32 {
+        if self.pending_len > 0 {
+            self.encode_pending();
+        }
+        if self.line_len > 0 {
+            self.tty.putc('\n');
+        }
+        self.crc.finish()
+    }
+}
+
+/// Send a screenshot of a display over a serial port.
+///
+/// On a buffered display, this reads the back buffer, so it shows whatever was last drawn,
+/// even if it hasn't been refreshed yet. Otherwise, it reads the screen itself, which may be slow.
+pub fn send(display: &dyn GraphicDisplay, serial: &mut SerialTty) {
+    let (width, height) = display.resolution();
+
+    // The PPM header is tiny, so we format it on the stack.
+    let mut header = HeaderBuffer { buf: [0; 32], len: 0 };
+    let _ = write!(header, "P6\n{} {}\n255\n", width, height);
+    let header = &header.buf[..header.len];
+
+    let _ = writeln!(serial, "=== bootproof screenshot begin: ppm {} ===", header.len() + width * height * 3);
+    let mut writer = Base64Writer::new(serial);
+    writer.write(header);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = unsafe { display.get_pixel(x, y) };
+            writer.write(&[pixel.r(), pixel.g(), pixel.b()]);
+        }
+    }
+    let crc = writer.finish();
+    let _ = writeln!(serial, "=== bootproof screenshot end: crc32 {:08x} ===", crc);
+}
+
+/// Just enough of a string buffer to format the PPM header without allocating.
+/// Anything past the end of the buffer is cut off, but the header can't be that long.
+struct HeaderBuffer {
+    buf: [u8; 32],
+    len: usize,
+}
+
+impl Write for HeaderBuffer {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+#!/usr/bin/env python3
+# Extract screenshots sent by the kernel (see `src/screenshot.rs`) from a log of its serial output.
+#
+# Boot the kernel with the `debug.screenshot` option and save the serial output, e.g.:
+#
+#     qemu-system-x86_64 ... -display none -serial file:serial.log
+#     tools/screenshot.py serial.log
+#
+# Each screenshot is checked against its length and CRC and saved as `screenshot-N.png`
+# (or `.ppm`, with `--ppm`). With no file, the log is read from standard input.
+
+import argparse
+import base64
+import re
+import struct
+import sys
+import zlib
+
+BEGIN = re.compile(r"=== bootproof screenshot begin: ppm (\d+) ===")
+END = re.compile(r"=== bootproof screenshot end: crc32 ([0-9a-f]{8}) ===")
+
+
+def extract(lines):
+    """Yield the decoded PPM data of each complete screenshot in the log."""
+    length = None
+    body = []
+    for number, line in enumerate(lines, 1):
+        line = line.strip()
+        begin = BEGIN.search(line)
+        if begin:
+            length, body = int(begin.group(1)), []
+            continue
+        if length is None:
+            continue
+
+        end = END.search(line)
+        if not end:
+            body.append(line)
+            continue
+
+        data = base64.b64decode("".join(body))
+        crc = int(end.group(1), 16)
+        if len(data) != length:
+            print(f"line {number}: expected {length} bytes, got {len(data)}; skipping", file=sys.stderr)
+        elif zlib.crc32(data) != crc:
+            print(f"line {number}: CRC doesn't match; skipping", file=sys.stderr)
+        else:
+            yield data
+        length = None
+
+
+def ppm_to_png(ppm):
+    """Convert a binary PPM with 8-bit channels to a PNG."""
+    magic, size, maxval, pixels = ppm.split(b"\n", 3)
+    assert magic == b"P6" and maxval == b"255"
+    width, height = map(int, size.split())
+
+    def chunk(kind, data):
+        return struct.pack(">I", len(data)) + kind + data + struct.pack(">I", zlib.crc32(kind + data))
+
+    stride = width * 3
+    # Each row starts with a filter type byte, which we always set to 0 (no filter).
+    raw = b"".join(b"\0" + pixels[y * stride:(y + 1) * stride] for y in range(height))
+    return (b"\x89PNG\r\n\x1a\n"
+            + chunk(b"IHDR", struct.pack(">IIBBBBB", width, height, 8, 2, 0, 0, 0))
+            + chunk(b"IDAT", zlib.compress(raw, 9))
+            + chunk(b"IEND", b""))
+
+
+def main():
+    parser = argparse.ArgumentParser(description="Extract screenshots from a log of the serial output.")
+    parser.add_argument("log", nargs="?", help="the serial log (default: standard input)")
+    parser.add_argument("--ppm", action="store_true", help="save screenshots as PPM instead of PNG")
+    parser.add_argument("--prefix", default="screenshot", help="the start of each file name")
+    args = parser.parse_args()
+
+    log = open(args.log, errors="replace") if args.log else sys.stdin
+    count = 0
+    for count, ppm in enumerate(extract(log), 1):
+        extension = "ppm" if args.ppm else "png"
+        path = f"{args.prefix}-{count}.{extension}"
+        with open(path, "wb") as file:
+            file.write(ppm if args.ppm else ppm_to_png(ppm))
+        print(path)
+
+    if count == 0:
+        print("no screenshots found", file=sys.stderr)
+        sys.exit(1)
+
+
+if __name__ == "__main__":
+    main()